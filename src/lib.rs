@@ -0,0 +1,3661 @@
+#![allow(clippy::upper_case_acronyms)]
+
+// The game itself: galaxy, entities, trading and saves
+// Frontends (the CLI, the JSON-RPC server) drive it through gm::GM
+
+// A file that couldn't be read or written
+// Shared by the config, item catalogue and save errors
+pub mod file_error {
+    use serde::Serialize;
+
+    #[derive(Debug, Clone, Serialize)]
+    pub struct FileError {
+        pub path: String,
+        #[serde(serialize_with = "kind_name")]
+        pub kind: std::io::ErrorKind,
+        pub message: String,
+    }
+    impl FileError {
+        pub fn io(path: &str, err: std::io::Error) -> Self {
+            FileError {
+                path: path.to_string(),
+                kind: err.kind(),
+                message: err.to_string(),
+            }
+        }
+        pub fn read(path: &str) -> Result<String, FileError> {
+            std::fs::read_to_string(path).map_err(|e| FileError::io(path, e))
+        }
+    }
+    impl std::fmt::Display for FileError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self.kind {
+                std::io::ErrorKind::NotFound => write!(f, "{} not found", self.path),
+                std::io::ErrorKind::PermissionDenied => {
+                    write!(f, "permission denied for {}", self.path)
+                }
+                _ => write!(f, "{}: {}", self.path, self.message),
+            }
+        }
+    }
+    impl std::error::Error for FileError {}
+
+    // ErrorKind has no serde support, report it by name
+    fn kind_name<S: serde::Serializer>(kind: &std::io::ErrorKind, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&format!("{:?}", kind))
+    }
+}
+
+// Universe settings, fixed for the life of a galaxy and saved with it
+pub mod univ {
+    use crate::file_error::FileError;
+    use crate::pos::Position;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[serde(default)]
+    pub struct Univ {
+        pub gal_size: i32,
+        pub starting_entities: i32,
+        pub fuel_cost_per_g: f32,
+        pub station_hold: i32,
+        pub station_credits: i32,
+        // The player's ship
+        pub start_credits: i32,
+        pub ship_hold: i32,
+        pub fuel_per_ly: i32,
+        pub max_range: i32,
+    }
+    impl Default for Univ {
+        fn default() -> Self {
+            Univ {
+                gal_size: 10000,
+                starting_entities: 10000,
+                fuel_cost_per_g: 0.2,
+                station_hold: 100000,
+                station_credits: 10000,
+                start_credits: 10000,
+                ship_hold: 1000,
+                fuel_per_ly: 10,
+                max_range: 100,
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    pub enum ConfigError {
+        // Reading the file failed
+        Io(FileError),
+        // The file isn't valid JSON or doesn't match the config layout
+        Parse(String),
+        // A value is out of range
+        Invalid(String),
+    }
+    impl std::fmt::Display for ConfigError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                ConfigError::Io(e) => write!(f, "{}", e),
+                ConfigError::Parse(msg) => write!(f, "corrupt config file: {}", msg),
+                ConfigError::Invalid(msg) => write!(f, "invalid universe setting: {}", msg),
+            }
+        }
+    }
+    impl std::error::Error for ConfigError {}
+
+    // Command line flags that override a config value, with their help text
+    pub const FLAGS: [(&str, &str); 6] = [
+        ("--gal-size", "width of the galaxy in ly"),
+        ("--entities", "number of stations to generate"),
+        ("--credits", "credits the player starts with"),
+        ("--hold", "cargo hold size of the player's ship"),
+        ("--fuel-per-ly", "fuel the player's drive burns per ly"),
+        ("--range", "max jump range of the player's drive in ly"),
+    ];
+
+    impl Univ {
+        // Read a JSON config, missing fields keep their defaults
+        pub fn load(path: &str) -> Result<Self, ConfigError> {
+            let json = FileError::read(path).map_err(ConfigError::Io)?;
+            let univ: Univ =
+                serde_json::from_str(&json).map_err(|e| ConfigError::Parse(e.to_string()))?;
+            univ.validate()?;
+            Ok(univ)
+        }
+        // Apply one of FLAGS
+        pub fn set_flag(&mut self, flag: &str, value: &str) -> Result<(), ConfigError> {
+            let num: i32 = value.parse().map_err(|_| {
+                ConfigError::Invalid(format!("{} needs a whole number, got '{}'", flag, value))
+            })?;
+            match flag {
+                "--gal-size" => self.gal_size = num,
+                "--entities" => self.starting_entities = num,
+                "--credits" => self.start_credits = num,
+                "--hold" => self.ship_hold = num,
+                "--fuel-per-ly" => self.fuel_per_ly = num,
+                "--range" => self.max_range = num,
+                _ => return Err(ConfigError::Invalid(format!("unknown flag {}", flag))),
+            }
+            self.validate()
+        }
+        pub fn validate(&self) -> Result<(), ConfigError> {
+            let positive = [
+                ("gal_size", self.gal_size),
+                ("station_hold", self.station_hold),
+                ("ship_hold", self.ship_hold),
+                ("fuel_per_ly", self.fuel_per_ly),
+                ("max_range", self.max_range),
+            ];
+            for (name, value) in positive {
+                if value <= 0 {
+                    return Err(ConfigError::Invalid(format!(
+                        "{} must be above 0, got {}",
+                        name, value
+                    )));
+                }
+            }
+            let not_negative = [
+                ("starting_entities", self.starting_entities),
+                ("station_credits", self.station_credits),
+                ("start_credits", self.start_credits),
+            ];
+            for (name, value) in not_negative {
+                if value < 0 {
+                    return Err(ConfigError::Invalid(format!(
+                        "{} can't be negative, got {}",
+                        name, value
+                    )));
+                }
+            }
+            if self.fuel_cost_per_g.is_nan() || self.fuel_cost_per_g <= 0.0 {
+                return Err(ConfigError::Invalid(
+                    "fuel_cost_per_g must be above 0".to_string(),
+                ));
+            }
+            // The drive's tank holds 8 max range jumps
+            let tank = self
+                .fuel_per_ly
+                .checked_mul(self.max_range)
+                .and_then(|f| f.checked_mul(8));
+            if tank.is_none() {
+                return Err(ConfigError::Invalid(
+                    "fuel_per_ly and max_range are too large together".to_string(),
+                ));
+            }
+            Ok(())
+        }
+        // Credits needed to buy `grams` of fuel
+        pub fn fuel_cost(&self, grams: i32) -> i32 {
+            (grams as f32 * self.fuel_cost_per_g) as i32
+        }
+        // Fuel that `credits` can buy
+        pub fn fuel_for(&self, credits: i32) -> i32 {
+            (credits as f32 / self.fuel_cost_per_g) as i32
+        }
+        // Stations sit in 0..gal_size, anything past a galaxy away is nonsense
+        pub fn in_bounds(&self, pos: &Position) -> bool {
+            let size = self.gal_size.unsigned_abs();
+            pos.x.unsigned_abs() <= size && pos.y.unsigned_abs() <= size
+        }
+    }
+}
+
+pub mod pos {
+    use rand::Rng;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+    pub struct Position {
+        pub x: i32,
+        pub y: i32,
+    }
+    impl std::fmt::Display for Position {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "X{} Y{}", self.x, self.y)
+        }
+    }
+    impl Position {
+        pub fn new(x: i32, y: i32) -> Self {
+            Position { x, y }
+        }
+        pub fn random(max: i32, rng: &mut impl Rng) -> Self {
+            Position {
+                x: rng.random_range(0..max),
+                y: rng.random_range(0..max),
+            }
+        }
+        // Saturates at i32::MAX for points too far apart
+        pub fn distance(&self, other: &Position) -> i32 {
+            let dx = (other.x as i64 - self.x as i64) as f64;
+            let dy = (other.y as i64 - self.y as i64) as f64;
+            (dx * dx + dy * dy).sqrt() as i32
+        }
+        // Squared distance, no sqrt
+        // Widened before subtracting so any two i32 points work
+        pub fn distance_sq(&self, other: &Position) -> i64 {
+            let dx = other.x as i64 - self.x as i64;
+            let dy = other.y as i64 - self.y as i64;
+            dx.saturating_mul(dx).saturating_add(dy.saturating_mul(dy))
+        }
+        // Same result as `distance(other) <= max` without the sqrt
+        pub fn within(&self, other: &Position, max: i32) -> bool {
+            if max < 0 {
+                return false;
+            }
+            let limit = max as i64 + 1;
+            self.distance_sq(other) < limit * limit
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn far_corners_dont_overflow() {
+            let low = Position::new(i32::MIN, i32::MIN);
+            let high = Position::new(i32::MAX, i32::MAX);
+            assert_eq!(low.distance(&high), i32::MAX);
+            assert_eq!(low.distance_sq(&high), i64::MAX);
+            assert!(!low.within(&high, i32::MAX));
+            assert_eq!(Position::new(-3, 0).distance(&Position::new(0, 4)), 5);
+        }
+    }
+}
+
+pub mod item_id {
+    use crate::item_meta::ILM;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    // Index into the loaded item catalogue
+    // Only handed out by the catalogue, so it always points at a real item
+    // Saved as the item's short name
+    #[derive(Hash, Eq, PartialEq, Ord, PartialOrd, Debug, Clone, Copy)]
+    pub struct ItemId(u16);
+
+    impl ItemId {
+        pub(crate) fn from_index(index: usize) -> Self {
+            ItemId(index as u16)
+        }
+        pub fn index(self) -> usize {
+            self.0 as usize
+        }
+    }
+
+    // Names items were saved under before the catalogue was data driven
+    const LEGACY_NAMES: [(&str, &str); 9] = [
+        ("MetalLow", "mtll"),
+        ("MetalMid", "mtlm"),
+        ("MetalHigh", "mtlh"),
+        ("CompositeLow", "cmpl"),
+        ("CompositeMid", "cmpm"),
+        ("CompositeHigh", "cmph"),
+        ("PolymerLow", "plyl"),
+        ("PolymerMid", "plym"),
+        ("PolymerHigh", "plyh"),
+    ];
+
+    impl Serialize for ItemId {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            match ILM.get(self) {
+                Some(meta) => serializer.serialize_str(&meta.sname),
+                None => Err(serde::ser::Error::custom(format!(
+                    "item index {} not in catalogue",
+                    self.0
+                ))),
+            }
+        }
+    }
+
+    impl<'de> Deserialize<'de> for ItemId {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let name = String::deserialize(deserializer)?;
+            let sname = LEGACY_NAMES
+                .iter()
+                .find(|(legacy, _)| *legacy == name)
+                .map_or(name.as_str(), |(_, sname)| sname);
+            ILM.id_by_sname(sname)
+                .ok_or_else(|| serde::de::Error::custom(format!("unknown item '{}'", name)))
+        }
+    }
+}
+
+pub mod item_meta {
+    use crate::file_error::FileError;
+    use crate::item_id::ItemId;
+    use serde::Deserialize;
+    use std::collections::HashMap;
+
+    pub const MAX_SNAME_LEN: usize = 4;
+
+    #[derive(Debug, Clone, Deserialize)]
+    pub struct ItemMeta {
+        pub fname: String,
+        pub sname: String, // 4 chars or less, unique
+        pub category: String,
+        pub vol_pc: i32,
+        pub base_val: i32,
+        pub rarity: i32, // 1-100
+    }
+
+    impl ItemMeta {
+        // 1.0 for common goods, rising with rarity
+        pub fn scarcity(&self) -> f64 {
+            1.0 + self.rarity as f64 / 25.0
+        }
+        // Percent of stations that deal in this item
+        pub fn stock_chance(&self) -> i32 {
+            (100 - self.rarity * 2).clamp(5, 100)
+        }
+    }
+
+    #[derive(Debug)]
+    pub enum ItemsError {
+        // Reading the file failed
+        Io(FileError),
+        // The file isn't valid JSON or doesn't match the item layout
+        Parse(String),
+        // The file parsed but an item in it is broken
+        Invalid(String),
+    }
+    impl std::fmt::Display for ItemsError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                ItemsError::Io(e) => write!(f, "{}", e),
+                ItemsError::Parse(msg) => write!(f, "corrupt item file: {}", msg),
+                ItemsError::Invalid(msg) => write!(f, "invalid item file: {}", msg),
+            }
+        }
+    }
+    impl std::error::Error for ItemsError {}
+
+    // fname, sname, category, vol_pc, base_val, rarity
+    type ItemRow = (&'static str, &'static str, &'static str, i32, i32, i32);
+    const BUILTIN_ITEMS: [ItemRow; 9] = [
+        ("Metals (low grade)", "mtll", "metal", 10, 10, 1),
+        ("Metals (mid grade)", "mtlm", "metal", 20, 20, 2),
+        ("Metals (high grade)", "mtlh", "metal", 30, 30, 3),
+        ("Composites (low grade)", "cmpl", "composite", 15, 25, 10),
+        ("Composites (mid grade)", "cmpm", "composite", 25, 35, 20),
+        ("Composites (high grade)", "cmph", "composite", 35, 45, 30),
+        ("Polymers (low grade)", "plyl", "polymer", 12, 20, 10),
+        ("Polymers (mid grade)", "plym", "polymer", 22, 30, 20),
+        ("Polymers (high grade)", "plyh", "polymer", 32, 40, 30),
+    ];
+
+    // The item catalogue, ItemId indexes into `items`
+    pub struct InvListMeta {
+        items: Vec<ItemMeta>,
+        by_sname: HashMap<String, ItemId>,
+    }
+    impl InvListMeta {
+        // Catalogue compiled into the game, used when there's no item file
+        pub fn builtin() -> Self {
+            let items = BUILTIN_ITEMS
+                .iter()
+                .map(
+                    |(fname, sname, category, vol_pc, base_val, rarity)| ItemMeta {
+                        fname: fname.to_string(),
+                        sname: sname.to_string(),
+                        category: category.to_string(),
+                        vol_pc: *vol_pc,
+                        base_val: *base_val,
+                        rarity: *rarity,
+                    },
+                )
+                .collect();
+            InvListMeta::from_items(items).expect("built-in items are valid")
+        }
+        // Read a JSON list of items
+        pub fn load(path: &str) -> Result<Self, ItemsError> {
+            let json = FileError::read(path).map_err(ItemsError::Io)?;
+            let items: Vec<ItemMeta> =
+                serde_json::from_str(&json).map_err(|e| ItemsError::Parse(e.to_string()))?;
+            InvListMeta::from_items(items)
+        }
+        pub fn from_items(mut items: Vec<ItemMeta>) -> Result<Self, ItemsError> {
+            if items.is_empty() {
+                return Err(ItemsError::Invalid("no items".to_string()));
+            }
+            if items.len() > u16::MAX as usize {
+                return Err(ItemsError::Invalid("too many items".to_string()));
+            }
+            let mut by_sname = HashMap::new();
+            for (index, meta) in items.iter_mut().enumerate() {
+                meta.sname = meta.sname.to_lowercase();
+                let sname = &meta.sname;
+                if sname.is_empty() || sname.chars().count() > MAX_SNAME_LEN {
+                    return Err(ItemsError::Invalid(format!(
+                        "short name '{}' must be 1 to {} characters",
+                        sname, MAX_SNAME_LEN
+                    )));
+                }
+                if !sname.chars().all(|c| c.is_ascii_alphanumeric()) {
+                    return Err(ItemsError::Invalid(format!(
+                        "short name '{}' must be letters and digits",
+                        sname
+                    )));
+                }
+                if meta.fname.is_empty() {
+                    return Err(ItemsError::Invalid(format!("{} has no name", sname)));
+                }
+                if meta.vol_pc <= 0 || meta.base_val <= 0 {
+                    return Err(ItemsError::Invalid(format!(
+                        "{} needs a positive volume and value",
+                        sname
+                    )));
+                }
+                if !(1..=100).contains(&meta.rarity) {
+                    return Err(ItemsError::Invalid(format!(
+                        "{} rarity {} is not 1-100",
+                        sname, meta.rarity
+                    )));
+                }
+                if by_sname
+                    .insert(sname.clone(), ItemId::from_index(index))
+                    .is_some()
+                {
+                    return Err(ItemsError::Invalid(format!(
+                        "duplicate short name '{}'",
+                        sname
+                    )));
+                }
+            }
+            Ok(InvListMeta { items, by_sname })
+        }
+        pub fn get(&self, item: &ItemId) -> Option<&ItemMeta> {
+            self.items.get(item.index())
+        }
+        // Get item id by short name
+        pub fn id_by_sname(&self, sname: &str) -> Option<ItemId> {
+            self.by_sname.get(&sname.to_lowercase()).copied()
+        }
+        // Get item meta by short name
+        pub fn get_by_sname(&self, sname: &str) -> Option<&ItemMeta> {
+            self.id_by_sname(sname).and_then(|id| self.get(&id))
+        }
+        // Every item, in catalogue order
+        pub fn ids(&self) -> impl Iterator<Item = ItemId> + use<> {
+            (0..self.items.len()).map(ItemId::from_index)
+        }
+    }
+
+    use std::sync::{LazyLock, OnceLock};
+    static CATALOGUE: OnceLock<InvListMeta> = OnceLock::new();
+    // Falls back to the built-in items if nothing was installed first
+    pub static ILM: LazyLock<&InvListMeta> =
+        LazyLock::new(|| CATALOGUE.get_or_init(InvListMeta::builtin));
+
+    // Must run before anything reads ILM, returns false if too late
+    pub fn install(catalogue: InvListMeta) -> bool {
+        CATALOGUE.set(catalogue).is_ok()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn item(sname: &str) -> ItemMeta {
+            ItemMeta {
+                fname: format!("Item {}", sname),
+                sname: sname.to_string(),
+                category: "test".to_string(),
+                vol_pc: 1,
+                base_val: 1,
+                rarity: 1,
+            }
+        }
+
+        fn invalid(snames: &[&str]) -> String {
+            match InvListMeta::from_items(snames.iter().map(|s| item(s)).collect()) {
+                Err(ItemsError::Invalid(msg)) => msg,
+                Err(e) => panic!("wrong error: {}", e),
+                Ok(_) => panic!("{:?} was accepted", snames),
+            }
+        }
+
+        #[test]
+        fn rejects_duplicate_snames() {
+            assert!(invalid(&["ab", "cd", "ab"]).contains("duplicate"));
+            // Short names are case insensitive
+            assert!(invalid(&["ab", "AB"]).contains("duplicate"));
+        }
+
+        #[test]
+        fn rejects_long_snames() {
+            assert!(InvListMeta::from_items(vec![item("abcd")]).is_ok());
+            assert!(invalid(&["abcde"]).contains("1 to 4"));
+            assert!(invalid(&[""]).contains("1 to 4"));
+        }
+    }
+}
+
+pub mod inv_store {
+    use crate::item_id::ItemId;
+    use crate::item_meta::ILM;
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    // Can be used for stock, price lists, etc.
+    pub struct InvStore(BTreeMap<ItemId, i32>);
+    impl Default for InvStore {
+        fn default() -> Self {
+            InvStore::new()
+        }
+    }
+    impl InvStore {
+        pub fn new() -> Self {
+            let mut store: InvStore = InvStore(BTreeMap::new());
+            for item in ILM.ids() {
+                store.0.insert(item, 0);
+            }
+            store
+        }
+        pub fn set(&mut self, item: ItemId, quantity: i32) {
+            self.0.insert(item, quantity);
+        }
+        pub fn get(&self, item: &ItemId) -> Option<&i32> {
+            self.0.get(item)
+        }
+        pub fn modify(&mut self, item: ItemId, delta: i32) {
+            let entry = self.0.entry(item).or_insert(0);
+            *entry += delta;
+            if *entry < 0 {
+                *entry = 0;
+            }
+        }
+        pub fn items(&self) -> Vec<(&ItemId, &i32)> {
+            self.0.iter().collect()
+        }
+    }
+}
+
+pub mod cargo_hold {
+    use crate::inv_store::InvStore;
+    use crate::item_id::ItemId;
+    use crate::item_meta::ILM;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Clone)]
+    pub enum HoldError {
+        OverCapacity { over: i32 },
+        NotEnough { have: i32 },
+        // Zero or less, take the other call to move goods the other way
+        InvalidQuantity { qty: i32 },
+    }
+    impl std::fmt::Display for HoldError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                HoldError::OverCapacity { over } => {
+                    write!(f, "would exceed capacity by {}", over)
+                }
+                HoldError::NotEnough { have } => write!(f, "only have {} pieces", have),
+                HoldError::InvalidQuantity { qty } => {
+                    write!(f, "quantity must be positive, got {}", qty)
+                }
+            }
+        }
+    }
+    impl std::error::Error for HoldError {}
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct CargoHold {
+        pub vol_max: i32,
+        pub vol: i32,
+        pub inv: InvStore,
+    }
+    impl CargoHold {
+        pub fn new(vol_max: i32) -> Self {
+            CargoHold {
+                vol_max,
+                vol: 0,
+                inv: InvStore::new(),
+            }
+        }
+        pub fn insert(&mut self, item: ItemId, quantity: i32) -> Result<(), HoldError> {
+            self.check_insert(&item, quantity)?;
+            self.inv.modify(item, quantity);
+            self.calc_vol();
+            Ok(())
+        }
+        pub fn remove(&mut self, item: ItemId, quantity: i32) -> Result<(), HoldError> {
+            self.check_remove(&item, quantity)?;
+            self.inv.modify(item, -quantity);
+            self.calc_vol();
+            Ok(())
+        }
+        // Check an insert without modifying the hold
+        pub fn check_insert(&self, item: &ItemId, quantity: i32) -> Result<(), HoldError> {
+            if quantity <= 0 {
+                return Err(HoldError::InvalidQuantity { qty: quantity });
+            }
+            // Saturating, a huge quantity is just very over capacity
+            let over = Self::item_vol(item)
+                .saturating_mul(quantity)
+                .saturating_add(self.vol)
+                .saturating_sub(self.vol_max);
+            if over > 0 {
+                return Err(HoldError::OverCapacity { over });
+            }
+            Ok(())
+        }
+        // Check a removal without modifying the hold
+        pub fn check_remove(&self, item: &ItemId, quantity: i32) -> Result<(), HoldError> {
+            if quantity <= 0 {
+                return Err(HoldError::InvalidQuantity { qty: quantity });
+            }
+            let have = self.qty(item);
+            if have < quantity {
+                return Err(HoldError::NotEnough { have });
+            }
+            Ok(())
+        }
+        pub fn qty(&self, item: &ItemId) -> i32 {
+            *self.inv.get(item).unwrap_or(&0)
+        }
+        pub fn free_volume(&self) -> i32 {
+            (self.vol_max - self.vol).max(0)
+        }
+        // Most pieces of an item that still fit
+        pub fn max_insertable(&self, item: &ItemId) -> i32 {
+            let vol_pc = Self::item_vol(item);
+            if vol_pc <= 0 {
+                return i32::MAX;
+            }
+            self.free_volume() / vol_pc
+        }
+        fn item_vol(item: &ItemId) -> i32 {
+            ILM.get(item).map_or(0, |meta| meta.vol_pc)
+        }
+        pub fn calc_vol(&mut self) {
+            let mut total_vol = 0;
+            for (item, qty) in self.inv.items() {
+                if let Some(meta) = ILM.get(item) {
+                    total_vol += meta.vol_pc * (*qty);
+                }
+            }
+            self.vol = total_vol;
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn rejects_zero_and_negative_quantities() {
+            let item = ILM.ids().next().unwrap();
+            let mut hold = CargoHold::new(1000);
+            hold.insert(item, 2).unwrap();
+            for qty in [0, -1, i32::MIN] {
+                let err = HoldError::InvalidQuantity { qty };
+                assert_eq!(hold.insert(item, qty), Err(err.clone()));
+                assert_eq!(hold.remove(item, qty), Err(err));
+            }
+            assert_eq!(hold.qty(&item), 2);
+        }
+
+        #[test]
+        fn huge_insert_is_over_capacity() {
+            let item = ILM.ids().next().unwrap();
+            let hold = CargoHold::new(1000);
+            assert!(matches!(
+                hold.check_insert(&item, i32::MAX),
+                Err(HoldError::OverCapacity { over }) if over > 0
+            ));
+        }
+    }
+}
+
+pub mod jump_drive {
+    use crate::gm::GMError;
+    use crate::pos;
+    use serde::{Deserialize, Serialize};
+
+    // What a jump that went ahead cost
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Jump {
+        pub distance: i32,
+        pub fuel_used: i32,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct JumpDrive {
+        pub fuel_per_ly: i32,
+        pub max_range: i32,
+        pub fuel_max: i32,
+        pub fuel_cur: i32,
+    }
+    impl JumpDrive {
+        pub fn new(fuel_per_ly: i32, max_range: i32) -> Self {
+            let fuel = fuel_per_ly * max_range * 8;
+            JumpDrive {
+                fuel_per_ly,
+                max_range,
+                fuel_max: fuel,
+                fuel_cur: fuel,
+            }
+        }
+        pub fn jump(&mut self, from: &pos::Position, to: &pos::Position) -> Result<Jump, GMError> {
+            let distance = from.distance(to);
+            self.check(distance)?;
+            let fuel_needed = self.calc_fuel(distance);
+            self.consume(fuel_needed);
+            Ok(Jump {
+                distance,
+                fuel_used: fuel_needed,
+            })
+        }
+        // Whether the drive can cover `distance` right now
+        pub fn check(&self, distance: i32) -> Result<(), GMError> {
+            if distance > self.max_range {
+                return Err(GMError::OutOfRange {
+                    distance,
+                    max_range: self.max_range,
+                });
+            }
+            let fuel_needed = self.calc_fuel(distance);
+            if fuel_needed > self.fuel_cur {
+                return Err(GMError::NotEnoughFuel {
+                    needed: fuel_needed,
+                    have: self.fuel_cur,
+                });
+            }
+            Ok(())
+        }
+        pub fn refuel_amt(&self) -> i32 {
+            self.fuel_max - self.fuel_cur
+        }
+        pub fn refuel(&mut self, amount: i32) {
+            self.fuel_cur += amount;
+            if self.fuel_cur > self.fuel_max {
+                self.fuel_cur = self.fuel_max;
+            }
+        }
+        pub fn print(&self) {
+            println!("Jump Drive: {}/{}g", self.fuel_cur, self.fuel_max);
+            println!("Max Range: {} ly", self.max_range);
+            println!("Fuel per ly: {}", self.fuel_per_ly);
+        }
+        pub fn calc_fuel(&self, distance: i32) -> i32 {
+            distance.saturating_mul(self.fuel_per_ly)
+        }
+        pub fn fuel_str(&self) -> String {
+            format!("{}/{} g", self.fuel_cur, self.fuel_max)
+        }
+        fn consume(&mut self, amount: i32) {
+            self.fuel_cur -= amount;
+            if self.fuel_cur < 0 {
+                self.fuel_cur = 0;
+            }
+        }
+    }
+}
+
+pub mod entity {
+    use crate::cargo_hold::CargoHold;
+    use crate::gm::GMError;
+    use crate::inv_store::InvStore;
+    use crate::item_id::ItemId;
+    use crate::jump_drive::Jump;
+    use crate::jump_drive::JumpDrive;
+    use crate::pos;
+    use crate::station_type::StationType;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct EntityFlags {
+        pub has_dock: bool,
+    }
+    impl Default for EntityFlags {
+        fn default() -> Self {
+            EntityFlags::new()
+        }
+    }
+    impl EntityFlags {
+        pub fn new() -> Self {
+            EntityFlags { has_dock: false }
+        }
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct EntityFinance {
+        pub credits: i32,
+        pub prices: InvStore,
+        pub profit_margin: i32, // Percentage
+        // Stock level a station is happy with, prices are base value here
+        #[serde(default = "InvStore::new")]
+        pub stock_target: InvStore,
+    }
+    impl Default for EntityFinance {
+        fn default() -> Self {
+            EntityFinance::new()
+        }
+    }
+    impl EntityFinance {
+        pub fn new() -> Self {
+            EntityFinance {
+                credits: 0,
+                prices: InvStore::new(),
+                profit_margin: 20,
+                stock_target: InvStore::new(),
+            }
+        }
+        // Split the margin around a mid price
+        // The ask is what this entity sells for, the bid is what it pays
+        pub fn spread(&self, mid: i32, selling: bool) -> i32 {
+            let half = self.profit_margin as f64 / 200.0;
+            let factor = if selling { 1.0 + half } else { 1.0 - half };
+            ((mid as f64 * factor).round() as i32).max(1)
+        }
+        pub fn ask(&self, item: &ItemId) -> i32 {
+            self.spread(*self.prices.get(item).unwrap_or(&0), true)
+        }
+        pub fn bid(&self, item: &ItemId) -> i32 {
+            self.spread(*self.prices.get(item).unwrap_or(&0), false)
+        }
+    }
+
+    #[derive(Hash, Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+    pub enum EntityClass {
+        Station,
+        Craft,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Entity {
+        pub name: String,
+        pub id: i32,
+        pub class: EntityClass,
+        pub pos: pos::Position,
+        pub fin: EntityFinance,
+        pub hold: CargoHold,
+        pub jump_drive: JumpDrive,
+        pub flags: EntityFlags,
+        pub docked_id: Option<i32>,
+        pub targeting_id: Option<i32>,
+        #[serde(default)]
+        pub station_type: Option<StationType>,
+    }
+    impl Entity {
+        pub fn new(name: &str) -> Self {
+            Entity {
+                name: name.to_string(),
+                id: 0, // Set my list.add
+                class: EntityClass::Craft,
+                pos: pos::Position::new(0, 0),
+                fin: EntityFinance::new(),
+                // Empty until entity_maker kits it out from Univ
+                hold: CargoHold::new(0),
+                jump_drive: JumpDrive::new(0, 0),
+                flags: EntityFlags::new(),
+                docked_id: None,
+                targeting_id: None,
+                station_type: None,
+            }
+        }
+        pub fn set_pos(&mut self, position: pos::Position) {
+            self.pos = position;
+        }
+        pub fn jump(&mut self, destination: &pos::Position) -> Result<Jump, GMError> {
+            if let Some(station_id) = self.docked_id {
+                return Err(GMError::Docked { station_id });
+            }
+            let jump = self.jump_drive.jump(&self.pos, destination)?;
+            self.pos = *destination;
+            Ok(jump)
+        }
+    }
+}
+
+// Station pricing
+// Prices follow stock: scarce goods cost more, gluts sell for less
+pub mod market {
+    use crate::entity::{Entity, EntityClass};
+    use crate::item_id::ItemId;
+    use crate::item_meta::ILM;
+
+    // How strongly price reacts to stock, 0.5 means 4x the stock halves the price
+    const ELASTICITY: f64 = 0.5;
+    // Price never moves further than this from base value
+    const MIN_FACTOR: f64 = 0.25;
+    const MAX_FACTOR: f64 = 4.0;
+
+    // Price of one piece at a given stock level
+    // Rare items react harder and can swing further
+    pub fn unit_price(item: &ItemId, stock: i32, target: i32) -> i32 {
+        let Some(meta) = ILM.get(item) else {
+            return 0;
+        };
+        if target <= 0 {
+            return meta.base_val;
+        }
+        let swing = meta.scarcity();
+        let ratio = target as f64 / stock.max(1) as f64;
+        let factor = ratio
+            .powf(ELASTICITY * swing)
+            .clamp(MIN_FACTOR / swing, MAX_FACTOR * swing);
+        ((meta.base_val as f64 * factor).round() as i32).max(1)
+    }
+
+    // Stations only deal in items they keep a target for
+    pub fn deals_in(ent: &Entity, item: &ItemId) -> bool {
+        ent.class != EntityClass::Station || *ent.fin.stock_target.get(item).unwrap_or(&0) > 0
+    }
+
+    // Total for `qty` pieces, each piece priced at the stock level it leaves
+    // `ent_sells` is true when `ent` is the one handing over the goods
+    // Sales go at the ask and purchases at the bid
+    pub fn trade_total(ent: &Entity, item: &ItemId, qty: i32, ent_sells: bool) -> i32 {
+        if ent.class != EntityClass::Station {
+            let price = if ent_sells {
+                ent.fin.ask(item)
+            } else {
+                ent.fin.bid(item)
+            };
+            return price * qty;
+        }
+        let stock = ent.hold.qty(item);
+        let target = *ent.fin.stock_target.get(item).unwrap_or(&0);
+        (0..qty)
+            .map(|i| {
+                let level = if ent_sells { stock - i } else { stock + i };
+                ent.fin.spread(unit_price(item, level, target), ent_sells)
+            })
+            .sum()
+    }
+
+    // Refresh the listed price of one item from current stock
+    pub fn reprice_item(ent: &mut Entity, item: &ItemId) {
+        if ent.class != EntityClass::Station {
+            return;
+        }
+        let target = *ent.fin.stock_target.get(item).unwrap_or(&0);
+        let price = unit_price(item, ent.hold.qty(item), target);
+        ent.fin.prices.set(*item, price);
+    }
+
+    pub fn reprice(ent: &mut Entity) {
+        for item in ILM.ids() {
+            reprice_item(ent, &item);
+        }
+    }
+}
+
+// What a station makes and uses up each tick
+pub mod station_type {
+    use crate::item_id::ItemId;
+    use crate::item_meta::ILM;
+    use crate::pos::Position;
+    use serde::{Deserialize, Serialize};
+    use std::sync::LazyLock;
+
+    // Declaration order must match STATION_TYPES
+    #[derive(Hash, Eq, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+    pub enum StationType {
+        Mine,
+        Refinery,
+        Fab,
+        Agri,
+        TradeHub,
+        Shipyard,
+    }
+
+    pub static STATION_TYPES: [StationType; 6] = [
+        StationType::Mine,
+        StationType::Refinery,
+        StationType::Fab,
+        StationType::Agri,
+        StationType::TradeHub,
+        StationType::Shipyard,
+    ];
+
+    impl std::fmt::Display for StationType {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let name = match self {
+                StationType::Mine => "Mine",
+                StationType::Refinery => "Refinery",
+                StationType::Fab => "Fab",
+                StationType::Agri => "Agri",
+                StationType::TradeHub => "Trade Hub",
+                StationType::Shipyard => "Shipyard",
+            };
+            write!(f, "{}", name)
+        }
+    }
+
+    // One production run, inputs are used up to make the outputs
+    pub struct Recipe {
+        pub inputs: Vec<(ItemId, i32)>,
+        pub outputs: Vec<(ItemId, i32)>,
+    }
+
+    // Recipes as written, by item short name
+    struct RecipeDef {
+        inputs: &'static [(&'static str, i32)],
+        outputs: &'static [(&'static str, i32)],
+    }
+
+    impl RecipeDef {
+        // None if the catalogue is missing any of its items
+        fn resolve(&self) -> Option<Recipe> {
+            let lookup = |list: &[(&str, i32)]| {
+                list.iter()
+                    .map(|(sname, qty)| ILM.id_by_sname(sname).map(|id| (id, *qty)))
+                    .collect::<Option<Vec<_>>>()
+            };
+            Some(Recipe {
+                inputs: lookup(self.inputs)?,
+                outputs: lookup(self.outputs)?,
+            })
+        }
+    }
+
+    // Resolved once against the loaded catalogue, in STATION_TYPES order
+    static RECIPES: LazyLock<Vec<Vec<Recipe>>> = LazyLock::new(|| {
+        STATION_TYPES
+            .iter()
+            .map(|t| t.recipe_defs().iter().filter_map(|d| d.resolve()).collect())
+            .collect()
+    });
+
+    // Rough rings around the galactic centre
+    // Raw goods come from the rim, finished goods from the core
+    #[derive(Eq, PartialEq, Debug, Clone, Copy)]
+    pub enum Region {
+        Core,
+        Belt,
+        Frontier,
+    }
+
+    impl Region {
+        pub fn of(pos: &Position, gal_size: i32) -> Region {
+            let half = (gal_size / 2).max(1);
+            let centre = Position::new(half, half);
+            let frac = pos.distance(&centre) as f64 / half as f64;
+            if frac < 0.35 {
+                Region::Core
+            } else if frac < 0.75 {
+                Region::Belt
+            } else {
+                Region::Frontier
+            }
+        }
+    }
+
+    impl StationType {
+        // Short tag for tables
+        pub fn code(&self) -> &'static str {
+            match self {
+                StationType::Mine => "MINE",
+                StationType::Refinery => "REFN",
+                StationType::Fab => "FAB",
+                StationType::Agri => "AGRI",
+                StationType::TradeHub => "HUB",
+                StationType::Shipyard => "YARD",
+            }
+        }
+        // Each recipe runs on its own once per tick
+        pub fn recipes(&self) -> &'static [Recipe] {
+            &RECIPES[*self as usize]
+        }
+        fn recipe_defs(&self) -> &'static [RecipeDef] {
+            match self {
+                StationType::Mine => &[RecipeDef {
+                    inputs: &[],
+                    outputs: &[("mtll", 3), ("mtlm", 1)],
+                }],
+                StationType::Agri => &[RecipeDef {
+                    inputs: &[],
+                    outputs: &[("plyl", 3)],
+                }],
+                StationType::Refinery => &[
+                    RecipeDef {
+                        inputs: &[("mtll", 3)],
+                        outputs: &[("mtlm", 1)],
+                    },
+                    RecipeDef {
+                        inputs: &[("mtlm", 2)],
+                        outputs: &[("mtlh", 1)],
+                    },
+                    RecipeDef {
+                        inputs: &[("plyl", 3)],
+                        outputs: &[("plym", 1)],
+                    },
+                ],
+                StationType::Fab => &[
+                    RecipeDef {
+                        inputs: &[("mtll", 2), ("plyl", 1)],
+                        outputs: &[("cmpl", 1)],
+                    },
+                    RecipeDef {
+                        inputs: &[("mtlm", 2), ("plym", 1)],
+                        outputs: &[("cmpm", 1)],
+                    },
+                    RecipeDef {
+                        inputs: &[("plym", 2)],
+                        outputs: &[("plyh", 1)],
+                    },
+                ],
+                StationType::Shipyard => &[RecipeDef {
+                    inputs: &[("mtlh", 2), ("cmpm", 1), ("plyh", 1)],
+                    outputs: &[("cmph", 1)],
+                }],
+                StationType::TradeHub => &[],
+            }
+        }
+        // Relative chance of being picked at generation in a region
+        pub fn weight(&self, region: Region) -> i32 {
+            match (self, region) {
+                (StationType::Mine, Region::Core) => 5,
+                (StationType::Mine, Region::Belt) => 20,
+                (StationType::Mine, Region::Frontier) => 35,
+                (StationType::Agri, Region::Core) => 5,
+                (StationType::Agri, Region::Belt) => 15,
+                (StationType::Agri, Region::Frontier) => 30,
+                (StationType::Refinery, Region::Core) => 10,
+                (StationType::Refinery, Region::Belt) => 25,
+                (StationType::Refinery, Region::Frontier) => 15,
+                (StationType::Fab, Region::Core) => 25,
+                (StationType::Fab, Region::Belt) => 20,
+                (StationType::Fab, Region::Frontier) => 5,
+                (StationType::Shipyard, Region::Core) => 20,
+                (StationType::Shipyard, Region::Belt) => 5,
+                (StationType::Shipyard, Region::Frontier) => 0,
+                (StationType::TradeHub, Region::Core) => 35,
+                (StationType::TradeHub, Region::Belt) => 15,
+                (StationType::TradeHub, Region::Frontier) => 15,
+            }
+        }
+    }
+}
+
+// Station production and stock recovery, run once per tick
+pub mod economy {
+    use crate::entity::{Entity, EntityClass};
+    use crate::item_id::ItemId;
+    use crate::item_meta::ILM;
+    use crate::market;
+    use crate::station_type::Recipe;
+
+    // Fraction of the gap to target stock closed each tick
+    const REGEN_RATE: f64 = 0.02;
+
+    pub fn step(ent: &mut Entity) {
+        if ent.class != EntityClass::Station {
+            return;
+        }
+        let produced = produce(ent);
+        let regenerated = regenerate(ent);
+        // Stations sitting at their targets need no refresh
+        if produced || regenerated {
+            ent.hold.calc_vol();
+            market::reprice(ent);
+        }
+    }
+
+    // Run each of the station's recipes once if it has the inputs and the room
+    fn produce(ent: &mut Entity) -> bool {
+        let Some(station_type) = ent.station_type else {
+            return false;
+        };
+        let mut changed = false;
+        for recipe in station_type.recipes() {
+            changed |= run_recipe(ent, recipe);
+        }
+        changed
+    }
+
+    fn run_recipe(ent: &mut Entity, recipe: &Recipe) -> bool {
+        for (item, qty) in recipe.inputs.iter() {
+            if ent.hold.check_remove(item, *qty).is_err() {
+                return false;
+            }
+        }
+        // The inputs make room as they're used up
+        let vol = |items: &[(ItemId, i32)]| -> i32 {
+            items
+                .iter()
+                .map(|(item, qty)| ILM.get(item).map_or(0, |meta| meta.vol_pc) * qty)
+                .sum()
+        };
+        if vol(&recipe.outputs) > ent.hold.free_volume() + vol(&recipe.inputs) {
+            return false;
+        }
+        for (item, qty) in recipe.inputs.iter() {
+            ent.hold.inv.modify(*item, -*qty);
+        }
+        for (item, qty) in recipe.outputs.iter() {
+            ent.hold.inv.modify(*item, *qty);
+        }
+        // Keep the volume right for the next recipe's room check
+        ent.hold.calc_vol();
+        true
+    }
+
+    // Outside trade pulls stock back toward the station's target
+    // which in turn pulls prices back toward base value
+    fn regenerate(ent: &mut Entity) -> bool {
+        let mut changed = false;
+        for item in ILM.ids() {
+            let target = *ent.fin.stock_target.get(&item).unwrap_or(&0);
+            let stock = ent.hold.qty(&item);
+            let gap = target - stock;
+            if gap == 0 {
+                continue;
+            }
+            // Rare goods trickle back slower
+            let rate = ILM
+                .get(&item)
+                .map_or(REGEN_RATE, |meta| REGEN_RATE / meta.scarcity());
+            let step = ((gap.abs() as f64 * rate).round() as i32).max(1);
+            // Restocking stops at a full hold, whatever the target says
+            let moved = if gap > 0 {
+                let step = step.min(ent.hold.max_insertable(&item));
+                step > 0 && ent.hold.insert(item, step).is_ok()
+            } else {
+                ent.hold.remove(item, step).is_ok()
+            };
+            changed |= moved;
+        }
+        changed
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::entity_maker;
+        use crate::univ::Univ;
+        use rand::SeedableRng;
+        use rand_chacha::ChaCha12Rng;
+
+        #[test]
+        fn stock_fits_a_small_hold() {
+            let univ = Univ {
+                station_hold: 500,
+                ..Univ::default()
+            };
+            let names = vec!["Tiny".to_string()];
+            let mut rng = ChaCha12Rng::seed_from_u64(3);
+            for _ in 0..50 {
+                let mut ent = entity_maker::station(&names, &univ, &mut rng);
+                assert!(ent.hold.vol <= ent.hold.vol_max);
+                for _ in 0..200 {
+                    step(&mut ent);
+                    assert!(ent.hold.vol <= ent.hold.vol_max, "{}", ent.hold.vol);
+                }
+            }
+        }
+    }
+}
+
+pub mod entity_maker {
+    use crate::cargo_hold::CargoHold;
+    use crate::entity::Entity;
+    use crate::entity::EntityClass;
+    use crate::item_meta::ILM;
+    use crate::jump_drive::JumpDrive;
+    use crate::market;
+    use crate::pos::Position;
+    use crate::station_type::{Region, STATION_TYPES, StationType};
+    use crate::univ::Univ;
+    use rand::Rng;
+
+    // The player's ship, kitted out as the universe says
+    pub fn ship(name: &str, univ: &Univ) -> Entity {
+        let mut ent = Entity::new(name);
+        ent.class = EntityClass::Craft;
+        ent.fin.credits = univ.start_credits;
+        ent.hold = CargoHold::new(univ.ship_hold);
+        ent.jump_drive = JumpDrive::new(univ.fuel_per_ly, univ.max_range);
+        ent
+    }
+    pub fn station(name_list: &[String], univ: &Univ, rng: &mut impl Rng) -> Entity {
+        fn random_name(station_names: &[String], rng: &mut impl Rng) -> String {
+            let index1 = rng.random_range(0..station_names.len());
+            let index2 = rng.random_range(0..station_names.len());
+            let index3 = rng.random_range(0..station_names.len());
+            format!(
+                "{}-{}-{}",
+                station_names[index1], station_names[index2], station_names[index3]
+            )
+        }
+        let mut ent = Entity::new(random_name(name_list, rng).as_str());
+        // Position first, the region it lands in decides the type
+        ent.set_pos(Position::random(univ.gal_size, rng));
+        ent.class = EntityClass::Station;
+        ent.fin.credits = univ.station_credits;
+        ent.hold = CargoHold::new(univ.station_hold);
+        fit_out(&mut ent, univ, rng);
+        ent.flags.has_dock = true;
+        ent
+    }
+
+    // Give a station whatever it lacks of a type and stock, then price it
+    // Also brings stations from old saves up to date
+    pub(crate) fn fit_out(ent: &mut Entity, univ: &Univ, rng: &mut impl Rng) {
+        if ent.station_type.is_none() {
+            ent.station_type = Some(random_type(Region::of(&ent.pos, univ.gal_size), rng));
+        }
+        // Stations deal in exactly the items they keep a target for
+        if !ILM.ids().any(|item| market::deals_in(ent, &item)) {
+            random_stock(ent, rng);
+        }
+        market::reprice(ent);
+    }
+
+    // Each station wants a different amount of each item
+    // and starts somewhere around that amount
+    // Rare items are carried by fewer stations and in smaller amounts
+    fn random_stock(ent: &mut Entity, rng: &mut impl Rng) {
+        let recipes = ent.station_type.map_or(&[][..], |t| t.recipes());
+        // Iterate in a fixed order so the same seed gives the same stock
+        for item in ILM.ids() {
+            let meta = ILM.get(&item).unwrap();
+            // Stations always deal in what their recipes use or make
+            let needed = recipes.iter().any(|r| {
+                r.inputs
+                    .iter()
+                    .chain(r.outputs.iter())
+                    .any(|(i, _)| *i == item)
+            });
+            if !needed && rng.random_range(0..100) >= meta.stock_chance() {
+                continue;
+            }
+            let target = ((rng.random_range(40..160) as f64 / meta.scarcity()) as i32).max(1);
+            let stock = target * rng.random_range(50..150) / 100;
+            ent.fin.stock_target.set(item, target);
+            // A small hold fills up before every item reaches its stock
+            let stock = stock.min(ent.hold.max_insertable(&item));
+            if stock > 0 {
+                ent.hold.insert(item, stock).unwrap();
+            }
+        }
+    }
+
+    fn random_type(region: Region, rng: &mut impl Rng) -> StationType {
+        let total: i32 = STATION_TYPES.iter().map(|t| t.weight(region)).sum();
+        let mut roll = rng.random_range(0..total);
+        for station_type in STATION_TYPES.iter() {
+            if roll < station_type.weight(region) {
+                return *station_type;
+            }
+            roll -= station_type.weight(region);
+        }
+        StationType::TradeHub
+    }
+}
+
+pub mod spatial {
+    use crate::pos::Position;
+    use std::collections::HashMap;
+
+    // Uniform grid over the galaxy
+    // Each cell holds the ids of the entities inside it
+    #[derive(Debug, Clone)]
+    pub struct SpatialGrid {
+        cell_size: i32,
+        cells: HashMap<(i32, i32), Vec<i32>>,
+    }
+    impl Default for SpatialGrid {
+        fn default() -> Self {
+            SpatialGrid::new(100)
+        }
+    }
+    impl SpatialGrid {
+        pub fn new(cell_size: i32) -> Self {
+            SpatialGrid {
+                cell_size: cell_size.max(1),
+                cells: HashMap::new(),
+            }
+        }
+        fn cell_of(&self, pos: &Position) -> (i32, i32) {
+            (
+                pos.x.div_euclid(self.cell_size),
+                pos.y.div_euclid(self.cell_size),
+            )
+        }
+        pub fn clear(&mut self) {
+            self.cells.clear();
+        }
+        pub fn insert(&mut self, id: i32, pos: &Position) {
+            let cell = self.cell_of(pos);
+            self.cells.entry(cell).or_default().push(id);
+        }
+        pub fn remove(&mut self, id: i32, pos: &Position) {
+            let cell = self.cell_of(pos);
+            if let Some(ids) = self.cells.get_mut(&cell) {
+                ids.retain(|other| *other != id);
+                if ids.is_empty() {
+                    self.cells.remove(&cell);
+                }
+            }
+        }
+        pub fn relocate(&mut self, id: i32, from: &Position, to: &Position) {
+            if self.cell_of(from) == self.cell_of(to) {
+                return;
+            }
+            self.remove(id, from);
+            self.insert(id, to);
+        }
+        // Number of cells a radius query would have to visit
+        pub fn cells_in_radius(&self, radius: i32) -> i64 {
+            let span = (2 * radius as i64) / self.cell_size as i64 + 2;
+            span * span
+        }
+        // Ids in every cell overlapping the square around origin
+        // Callers still need to check the exact distance
+        pub fn candidates(&self, origin: &Position, radius: i32) -> Vec<i32> {
+            let min = self.cell_of(&Position::new(
+                origin.x.saturating_sub(radius),
+                origin.y.saturating_sub(radius),
+            ));
+            let max = self.cell_of(&Position::new(
+                origin.x.saturating_add(radius),
+                origin.y.saturating_add(radius),
+            ));
+            let mut ids = Vec::new();
+            for cx in min.0..=max.0 {
+                for cy in min.1..=max.1 {
+                    if let Some(cell) = self.cells.get(&(cx, cy)) {
+                        ids.extend_from_slice(cell);
+                    }
+                }
+            }
+            ids
+        }
+        // Ids in the ring of cells exactly `ring` cells away from origin's cell
+        pub fn ring(&self, origin: &Position, ring: i32) -> Vec<i32> {
+            let (ox, oy) = self.cell_of(origin);
+            let mut ids = Vec::new();
+            // Cells past what an i32 holds can't have anything in them
+            let mut visit = |cx: i64, cy: i64| {
+                let cell = (i32::try_from(cx), i32::try_from(cy));
+                if let (Ok(cx), Ok(cy)) = cell
+                    && let Some(found) = self.cells.get(&(cx, cy))
+                {
+                    ids.extend_from_slice(found);
+                }
+            };
+            let (ox, oy, ring) = (ox as i64, oy as i64, ring as i64);
+            if ring == 0 {
+                visit(ox, oy);
+                return ids;
+            }
+            for d in -ring..=ring {
+                visit(ox + d, oy - ring);
+                visit(ox + d, oy + ring);
+            }
+            for d in (-ring + 1)..ring {
+                visit(ox - ring, oy + d);
+                visit(ox + ring, oy + d);
+            }
+            ids
+        }
+        // Number of cells in a ring
+        pub fn ring_cells(ring: i32) -> i64 {
+            if ring == 0 { 1 } else { 8 * ring as i64 }
+        }
+        // Anything outside the first `ring` rings is at least this far away
+        pub fn ring_reach(&self, ring: i32) -> i64 {
+            ring as i64 * self.cell_size as i64
+        }
+    }
+}
+
+pub mod entity_list {
+    use crate::entity::Entity;
+    use crate::entity_maker;
+    use crate::gm::GMError;
+    use crate::jump_drive::Jump;
+    use crate::pos::Position;
+    use crate::spatial::SpatialGrid;
+    use crate::univ::Univ;
+    use rand::Rng;
+    use serde::{Deserialize, Serialize};
+
+    // Positions must only change through `jump`, or be followed by `reindex`
+    // Otherwise the spatial grid falls out of sync
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct EntityList {
+        id_acc: i32,
+        entities: Vec<Entity>,
+        // Not saved, rebuilt by `reindex` after loading
+        #[serde(skip)]
+        grid: SpatialGrid,
+    }
+    impl Default for EntityList {
+        fn default() -> Self {
+            EntityList::new()
+        }
+    }
+    impl EntityList {
+        pub fn new() -> Self {
+            EntityList {
+                entities: Vec::new(),
+                id_acc: 0,
+                grid: SpatialGrid::default(),
+            }
+        }
+        pub fn add(&mut self, mut entity: Entity) {
+            entity.id = self.id_acc;
+            self.grid.insert(entity.id, &entity.pos);
+            self.entities.push(entity);
+            self.id_acc += 1;
+        }
+        // Sanity checks for a list that came from outside, like a save file
+        pub fn validate(&self) -> Result<(), String> {
+            match self.entities.first() {
+                Some(player) if player.id == 0 => {}
+                Some(_) => return Err("first entity is not the player (id 0)".to_string()),
+                None => return Err("no entities, the player is missing".to_string()),
+            }
+            let mut seen = std::collections::HashSet::new();
+            for ent in self.entities.iter() {
+                if ent.id < 0 {
+                    return Err(format!("negative entity id {}", ent.id));
+                }
+                if !seen.insert(ent.id) {
+                    return Err(format!("duplicate entity id {}", ent.id));
+                }
+                if ent.id >= self.id_acc {
+                    return Err(format!(
+                        "entity id {} is not below id_acc {}",
+                        ent.id, self.id_acc
+                    ));
+                }
+            }
+            for ent in self.entities.iter() {
+                for link in [ent.docked_id, ent.targeting_id].into_iter().flatten() {
+                    if !seen.contains(&link) {
+                        return Err(format!(
+                            "entity id {} refers to missing entity id {}",
+                            ent.id, link
+                        ));
+                    }
+                }
+            }
+            Ok(())
+        }
+        // Rebuild the spatial grid from scratch
+        pub fn reindex(&mut self) {
+            self.grid.clear();
+            for ent in self.entities.iter() {
+                self.grid.insert(ent.id, &ent.pos);
+            }
+        }
+        pub fn get(&self, index: i32) -> Option<&Entity> {
+            self.entities.get(index as usize)
+        }
+        // Mutable access stays in the crate so positions can't skip the grid
+        pub(crate) fn get_mut(&mut self, index: i32) -> Option<&mut Entity> {
+            self.entities.get_mut(index as usize)
+        }
+        // Ids are handed out in order so the id is usually the index
+        fn index_of(&self, id: i32) -> Option<usize> {
+            match self.entities.get(id as usize) {
+                Some(ent) if ent.id == id => Some(id as usize),
+                _ => self.entities.iter().position(|ent| ent.id == id),
+            }
+        }
+        pub fn get_by_id(&self, id: i32) -> Option<&Entity> {
+            self.index_of(id).map(|i| &self.entities[i])
+        }
+        pub(crate) fn get_by_id_mut(&mut self, id: i32) -> Option<&mut Entity> {
+            self.index_of(id).map(|i| &mut self.entities[i])
+        }
+        pub fn generate_entities(
+            &mut self,
+            name_list: &[String],
+            univ: &Univ,
+            count: usize,
+            rng: &mut impl Rng,
+        ) {
+            for _ in 0..count {
+                let ent = entity_maker::station(name_list, univ, rng);
+                self.add(ent);
+            }
+        }
+        pub fn get_player(&self) -> Option<&Entity> {
+            self.get(0)
+        }
+        pub(crate) fn get_player_mut(&mut self) -> Option<&mut Entity> {
+            self.get_mut(0)
+        }
+        // Jump an entity and keep the grid up to date
+        pub fn jump(&mut self, id: i32, destination: &Position) -> Result<Jump, GMError> {
+            let ent = self
+                .get_by_id_mut(id)
+                .ok_or(GMError::UnknownEntity { ent_id: id })?;
+            let from = ent.pos;
+            let jump = ent.jump(destination)?;
+            self.grid.relocate(id, &from, destination);
+            Ok(jump)
+        }
+        pub fn list(&self) -> Vec<&Entity> {
+            self.entities.iter().collect()
+        }
+        // Positions changed through this iterator are not reindexed
+        pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = &mut Entity> {
+            self.entities.iter_mut()
+        }
+        pub fn list_by_distance(&self, origin: Position, max_distance: i32) -> Vec<&Entity> {
+            // A huge radius touches more cells than a plain scan
+            let use_grid = self.grid.cells_in_radius(max_distance) < self.entities.len() as i64;
+            let mut found: Vec<(i64, &Entity)> = if use_grid {
+                self.grid
+                    .candidates(&origin, max_distance)
+                    .into_iter()
+                    .filter_map(|id| self.get_by_id(id))
+                    .filter(|ent| origin.within(&ent.pos, max_distance))
+                    .map(|ent| (origin.distance_sq(&ent.pos), ent))
+                    .collect()
+            } else {
+                self.entities
+                    .iter()
+                    .filter(|ent| origin.within(&ent.pos, max_distance))
+                    .map(|ent| (origin.distance_sq(&ent.pos), ent))
+                    .collect()
+            };
+            // Sort by distance, ties by id so the order is stable
+            found.sort_by_key(|(dist, ent)| (*dist, ent.id));
+            found.into_iter().map(|(_, ent)| ent).collect()
+        }
+        // The `count` entities closest to origin, ties by id like list_by_distance
+        pub fn list_nearest(&self, origin: Position, count: usize) -> Vec<&Entity> {
+            let count = count.min(self.entities.len());
+            if count == 0 {
+                return Vec::new();
+            }
+            let mut found: Vec<(i64, &Entity)> = Vec::new();
+            let mut visited = 0;
+            let mut ring = 0;
+            loop {
+                for id in self.grid.ring(&origin, ring) {
+                    if let Some(ent) = self.get_by_id(id) {
+                        found.push((origin.distance_sq(&ent.pos), ent));
+                    }
+                }
+                let reach = self.grid.ring_reach(ring);
+                if found.len() >= count {
+                    found.sort_by_key(|(dist, ent)| (*dist, ent.id));
+                    if found[count - 1].0 <= reach * reach {
+                        break;
+                    }
+                }
+                if found.len() == self.entities.len() {
+                    break;
+                }
+                // A few far off entities would take more cells than a plain scan
+                visited += SpatialGrid::ring_cells(ring);
+                if visited > self.entities.len() as i64 {
+                    found = self
+                        .entities
+                        .iter()
+                        .map(|ent| (origin.distance_sq(&ent.pos), ent))
+                        .collect();
+                    break;
+                }
+                ring += 1;
+            }
+            found.sort_by_key(|(dist, ent)| (*dist, ent.id));
+            found.truncate(count);
+            found.into_iter().map(|(_, ent)| ent).collect()
+        }
+        pub fn print(&self, ship: &Entity) {
+            for ent in self.entities.iter() {
+                let distance = ship.pos.distance(&ent.pos);
+                println!(
+                    "{}: {} [{}] ({} {})",
+                    ent.id, ent.name, distance, ent.pos.x, ent.pos.y
+                );
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::entity_maker;
+        use crate::jump_drive::JumpDrive;
+        use rand::SeedableRng;
+        use rand_chacha::ChaCha12Rng;
+
+        // What the grid has to agree with, every entity checked by hand
+        fn linear(list: &EntityList, origin: Position, max: i32) -> Vec<i32> {
+            let mut found: Vec<&Entity> = list
+                .list()
+                .into_iter()
+                .filter(|ent| origin.distance(&ent.pos) <= max)
+                .collect();
+            found.sort_by_key(|ent| (origin.distance_sq(&ent.pos), ent.id));
+            found.iter().map(|ent| ent.id).collect()
+        }
+
+        fn grid(list: &EntityList, origin: Position, max: i32) -> Vec<i32> {
+            let found = list.list_by_distance(origin, max);
+            found.iter().map(|ent| ent.id).collect()
+        }
+
+        // Enough entities that small radii go through the grid
+        fn scattered(count: usize, rng: &mut ChaCha12Rng) -> EntityList {
+            let mut list = EntityList::new();
+            for _ in 0..count {
+                let mut ent = Entity::new("Station");
+                ent.set_pos(Position::random(1000, rng));
+                list.add(ent);
+            }
+            list
+        }
+
+        fn assert_matches_scan(list: &EntityList) {
+            let origins = [
+                Position::new(0, 0),
+                Position::new(500, 500),
+                Position::new(100, 100),
+                Position::new(99, 199),
+                Position::new(-1, -1),
+                Position::new(1000, 1000),
+            ];
+            let radii = [0, 1, 50, 99, 100, 101, 250, 2000, i32::MAX];
+            for origin in origins {
+                for max in radii {
+                    assert_eq!(
+                        grid(list, origin, max),
+                        linear(list, origin, max),
+                        "origin {} radius {}",
+                        origin,
+                        max
+                    );
+                }
+            }
+        }
+
+        #[test]
+        fn grid_matches_scan() {
+            let mut rng = ChaCha12Rng::seed_from_u64(5);
+            assert_matches_scan(&scattered(2000, &mut rng));
+        }
+
+        #[test]
+        fn grid_matches_scan_at_cell_edges() {
+            let mut list = EntityList::new();
+            for (x, y) in [(99, 0), (100, 0), (199, 0), (200, 0), (0, 99), (0, 100)] {
+                let mut ent = Entity::new("Edge");
+                ent.set_pos(Position::new(x, y));
+                list.add(ent);
+            }
+            // Filler so the grid is used
+            let mut rng = ChaCha12Rng::seed_from_u64(6);
+            for _ in 0..200 {
+                let mut ent = Entity::new("Far");
+                ent.set_pos(Position::new(5000 + rng.random_range(0..100), 5000));
+                list.add(ent);
+            }
+            // 100 and 200 are exactly 50 away, both count
+            assert_eq!(grid(&list, Position::new(150, 0), 50), vec![2, 1, 3]);
+            assert_eq!(grid(&list, Position::new(0, 50), 50), vec![4, 5]);
+            assert_matches_scan(&list);
+        }
+
+        fn assert_nearest_matches_scan(list: &EntityList) {
+            let origins = [
+                Position::new(0, 0),
+                Position::new(500, 500),
+                Position::new(99, 199),
+                Position::new(-1, -1),
+                Position::new(5000, -5000),
+            ];
+            for origin in origins {
+                for count in [0, 1, 2, 7, 50, 500, list.list().len() + 1] {
+                    let mut expected = linear(list, origin, i32::MAX);
+                    expected.truncate(count);
+                    let found: Vec<i32> = list
+                        .list_nearest(origin, count)
+                        .iter()
+                        .map(|ent| ent.id)
+                        .collect();
+                    assert_eq!(found, expected, "origin {} count {}", origin, count);
+                }
+            }
+        }
+
+        #[test]
+        fn nearest_matches_scan() {
+            let mut rng = ChaCha12Rng::seed_from_u64(8);
+            let mut list = scattered(2000, &mut rng);
+            assert_nearest_matches_scan(&list);
+            // A few strays far away, reaching them falls back to the scan
+            for (x, y) in [(i32::MAX, i32::MAX), (i32::MIN, 0), (-90_000, 40_000)] {
+                let mut ent = Entity::new("Stray");
+                ent.set_pos(Position::new(x, y));
+                list.add(ent);
+            }
+            assert_nearest_matches_scan(&list);
+        }
+
+        // A saved list with entities of the given ids, as a save file holds it
+        fn list_json(ids: &[i32], id_acc: i32) -> String {
+            let entities: Vec<serde_json::Value> = ids
+                .iter()
+                .map(|id| {
+                    let mut ent = serde_json::to_value(Entity::new("Ent")).unwrap();
+                    ent["id"] = (*id).into();
+                    ent
+                })
+                .collect();
+            serde_json::json!({ "id_acc": id_acc, "entities": entities }).to_string()
+        }
+
+        fn validate(ids: &[i32], id_acc: i32) -> Result<(), String> {
+            let list: EntityList = serde_json::from_str(&list_json(ids, id_acc)).unwrap();
+            list.validate()
+        }
+
+        #[test]
+        fn validate_rejects_broken_lists() {
+            assert_eq!(validate(&[0, 1, 2], 3), Ok(()));
+            assert!(validate(&[], 0).unwrap_err().contains("player"));
+            assert!(validate(&[1, 0], 2).unwrap_err().contains("player"));
+            assert!(validate(&[0, 1, 1], 3).unwrap_err().contains("duplicate"));
+            assert!(validate(&[0, -1], 2).unwrap_err().contains("negative"));
+            assert!(validate(&[0, 1, 2], 2).unwrap_err().contains("id_acc"));
+        }
+
+        #[test]
+        fn grid_follows_moves() {
+            let mut rng = ChaCha12Rng::seed_from_u64(7);
+            let mut list = EntityList::new();
+            let mut ship = entity_maker::ship("Ship", &crate::univ::Univ::default());
+            ship.set_pos(Position::new(500, 500));
+            list.add(ship);
+            for _ in 0..2000 {
+                let mut ent = Entity::new("Station");
+                ent.set_pos(Position::random(1000, &mut rng));
+                // Enough drive to cross the whole area
+                ent.jump_drive = JumpDrive::new(1, 2000);
+                list.add(ent);
+            }
+
+            list.jump(0, &Position::new(560, 430)).unwrap();
+            for id in 1..500 {
+                list.jump(id, &Position::random(1000, &mut rng)).unwrap();
+            }
+            assert_matches_scan(&list);
+
+            // Moved behind the grid's back, only right again after reindex
+            for ent in list.iter_mut() {
+                ent.pos = Position::new(ent.pos.y, ent.pos.x);
+            }
+            list.reindex();
+            assert_matches_scan(&list);
+        }
+    }
+}
+
+pub mod route {
+    use crate::entity::Entity;
+    use crate::entity_list::EntityList;
+    use crate::pos::Position;
+    use crate::univ::Univ;
+    use serde::Serialize;
+    use std::cmp::Reverse;
+    use std::collections::{BinaryHeap, HashMap};
+
+    // Node id used for the ship's starting position
+    const START: i32 = -1;
+    // Hop count dominates distance when minimizing hops
+    const HOP_WEIGHT: i64 = 1 << 40;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum RouteMode {
+        Hops,
+        Fuel,
+    }
+    impl RouteMode {
+        pub fn parse(s: &str) -> Option<Self> {
+            match s {
+                "hops" | "h" => Some(RouteMode::Hops),
+                "fuel" | "f" => Some(RouteMode::Fuel),
+                _ => None,
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Serialize)]
+    pub struct RouteHop {
+        pub ent_id: i32,
+        pub pos: Position,
+        pub distance: i32,
+        pub fuel: i32,
+        // Fuel bought before setting off on this leg
+        pub refuel: i32,
+        pub refuel_cost: i32,
+    }
+
+    #[derive(Debug, Clone, Serialize)]
+    pub struct Route {
+        pub hops: Vec<RouteHop>,
+        pub distance: i32,
+        pub fuel: i32,
+        pub cost: i32,
+        pub refuel_stops: i32,
+    }
+
+    // Exact distance in thousandths of a light year
+    fn milli_ly(a: &Position, b: &Position) -> i64 {
+        ((a.distance_sq(b) as f64).sqrt() * 1000.0) as i64
+    }
+
+    // A* over dockable stations, every leg within jump range
+    // Assumes fuel can be bought at any station on the way
+    pub fn plan(
+        entities: &EntityList,
+        univ: &Univ,
+        ship: &Entity,
+        target_id: i32,
+        mode: RouteMode,
+    ) -> Option<Route> {
+        let target = entities.get_by_id(target_id)?;
+        let goal = target.pos;
+        let drive = &ship.jump_drive;
+        let max_range = drive.max_range;
+        // Can't refuel in open space, so the first leg is limited by the tank
+        let start_refuel = ship.docked_id.is_some();
+        let start_range = if start_refuel || drive.fuel_per_ly <= 0 {
+            max_range
+        } else {
+            max_range.min(drive.fuel_cur / drive.fuel_per_ly)
+        };
+
+        let heuristic = |pos: &Position| -> i64 {
+            let straight = milli_ly(pos, &goal);
+            match mode {
+                RouteMode::Fuel => straight,
+                RouteMode::Hops => {
+                    // A legal hop is always shorter than max_range + 1
+                    let reach = (max_range as i64 + 1) * 1000;
+                    (straight + reach - 1) / reach * HOP_WEIGHT + straight
+                }
+            }
+        };
+        let edge_cost = |from: &Position, to: &Position| -> i64 {
+            match mode {
+                RouteMode::Fuel => milli_ly(from, to),
+                RouteMode::Hops => HOP_WEIGHT + milli_ly(from, to),
+            }
+        };
+
+        let mut best: HashMap<i32, i64> = HashMap::new();
+        let mut parent: HashMap<i32, i32> = HashMap::new();
+        let mut open = BinaryHeap::new();
+        best.insert(START, 0);
+        open.push(Reverse((heuristic(&ship.pos), 0i64, START)));
+
+        while let Some(Reverse((_, cost, node))) = open.pop() {
+            if node == target_id {
+                break;
+            }
+            // Skip stale queue entries
+            if best.get(&node).is_some_and(|b| *b < cost) {
+                continue;
+            }
+            let (pos, range) = if node == START {
+                (ship.pos, start_range)
+            } else {
+                (entities.get_by_id(node)?.pos, max_range)
+            };
+            for next in entities.list_by_distance(pos, range) {
+                if next.id == node || next.id == ship.id {
+                    continue;
+                }
+                if !next.flags.has_dock && next.id != target_id {
+                    continue;
+                }
+                let next_cost = cost + edge_cost(&pos, &next.pos);
+                if best.get(&next.id).is_none_or(|b| next_cost < *b) {
+                    best.insert(next.id, next_cost);
+                    parent.insert(next.id, node);
+                    open.push(Reverse((
+                        next_cost + heuristic(&next.pos),
+                        next_cost,
+                        next.id,
+                    )));
+                }
+            }
+        }
+        if !parent.contains_key(&target_id) {
+            return None;
+        }
+
+        // Walk back from the target to get the hop order
+        let mut ids = vec![target_id];
+        let mut node = target_id;
+        while let Some(prev) = parent.get(&node) {
+            if *prev == START {
+                break;
+            }
+            ids.push(*prev);
+            node = *prev;
+        }
+        ids.reverse();
+
+        // Replay the route to work out where fuel has to be bought
+        let mut route = Route {
+            hops: Vec::new(),
+            distance: 0,
+            fuel: 0,
+            cost: 0,
+            refuel_stops: 0,
+        };
+        let mut fuel = drive.fuel_cur;
+        let mut from = ship.pos;
+        for (i, id) in ids.iter().enumerate() {
+            let ent = entities.get_by_id(*id)?;
+            let distance = from.distance(&ent.pos);
+            let leg_fuel = drive.calc_fuel(distance);
+            let mut refuel = 0;
+            if leg_fuel > fuel && (i > 0 || start_refuel) {
+                refuel = drive.fuel_max - fuel;
+            }
+            let refuel_cost = univ.fuel_cost(refuel);
+            fuel += refuel;
+            fuel -= leg_fuel;
+            if refuel > 0 {
+                route.refuel_stops += 1;
+            }
+            route.distance += distance;
+            route.fuel += leg_fuel;
+            route.cost += refuel_cost;
+            route.hops.push(RouteHop {
+                ent_id: *id,
+                pos: ent.pos,
+                distance,
+                fuel: leg_fuel,
+                refuel,
+                refuel_cost,
+            });
+            from = ent.pos;
+        }
+        Some(route)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::entity_maker;
+
+        // Player ship at the origin, then a dockable station per position
+        fn galaxy(stations: &[(i32, i32)]) -> EntityList {
+            let mut list = EntityList::new();
+            list.add(entity_maker::ship("Ship", &Univ::default()));
+            for (x, y) in stations {
+                let mut ent = Entity::new("Station");
+                ent.set_pos(Position::new(*x, *y));
+                ent.flags.has_dock = true;
+                list.add(ent);
+            }
+            list
+        }
+
+        fn hop_ids(route: &Route) -> Vec<i32> {
+            route.hops.iter().map(|h| h.ent_id).collect()
+        }
+
+        #[test]
+        fn fuel_and_hops_modes_pick_different_routes() {
+            // A detour through 1 takes two hops, the straight line takes three
+            let list = galaxy(&[(90, 40), (60, 0), (120, 0), (180, 0)]);
+            let univ = Univ::default();
+            let ship = list.get_player().unwrap();
+
+            let fuel = plan(&list, &univ, ship, 4, RouteMode::Fuel).unwrap();
+            assert_eq!(hop_ids(&fuel), vec![2, 3, 4]);
+            assert_eq!(fuel.distance, 180);
+            assert_eq!(fuel.fuel, 1800);
+
+            let hops = plan(&list, &univ, ship, 4, RouteMode::Hops).unwrap();
+            assert_eq!(hop_ids(&hops), vec![1, 4]);
+            assert!(hops.distance > fuel.distance);
+        }
+
+        #[test]
+        fn refuels_when_the_tank_runs_dry() {
+            let list = galaxy(&[(100, 0), (200, 0), (300, 0)]);
+            let univ = Univ::default();
+            // A tank that holds exactly one full-range jump
+            let mut ship = list.get_player().unwrap().clone();
+            ship.jump_drive.fuel_max = 1000;
+            ship.jump_drive.fuel_cur = 1000;
+
+            let route = plan(&list, &univ, &ship, 3, RouteMode::Fuel).unwrap();
+            assert_eq!(hop_ids(&route), vec![1, 2, 3]);
+            let refuels: Vec<i32> = route.hops.iter().map(|h| h.refuel).collect();
+            assert_eq!(refuels, vec![0, 1000, 1000]);
+            assert_eq!(route.refuel_stops, 2);
+            assert_eq!(route.cost, 2 * univ.fuel_cost(1000));
+
+            // Docked with an empty tank, the first leg is bought up front
+            ship.jump_drive.fuel_cur = 0;
+            ship.docked_id = Some(1);
+            ship.set_pos(Position::new(100, 0));
+            let route = plan(&list, &univ, &ship, 3, RouteMode::Fuel).unwrap();
+            assert_eq!(hop_ids(&route), vec![2, 3]);
+            assert_eq!(route.refuel_stops, 2);
+        }
+
+        #[test]
+        fn first_leg_limited_by_fuel_in_open_space() {
+            let list = galaxy(&[(40, 0), (100, 0)]);
+            let univ = Univ::default();
+            let mut ship = list.get_player().unwrap().clone();
+            // Enough for 50 ly, so 2 is in range but not reachable directly
+            ship.jump_drive.fuel_cur = 500;
+
+            let route = plan(&list, &univ, &ship, 2, RouteMode::Hops).unwrap();
+            assert_eq!(hop_ids(&route), vec![1, 2]);
+            assert_eq!(route.hops[1].refuel, ship.jump_drive.fuel_max - 100);
+        }
+
+        #[test]
+        fn unreachable_target_has_no_route() {
+            let list = galaxy(&[(100, 0), (350, 0)]);
+            let univ = Univ::default();
+            let ship = list.get_player().unwrap();
+            assert!(plan(&list, &univ, ship, 2, RouteMode::Fuel).is_none());
+            assert!(plan(&list, &univ, ship, 2, RouteMode::Hops).is_none());
+            // No such entity
+            assert!(plan(&list, &univ, ship, 99, RouteMode::Fuel).is_none());
+        }
+    }
+}
+
+// Actions represent things the player can do
+// They should never print anything
+// Formatting and UX is up to ui functions
+// Galaxy Manager
+pub mod gm {
+
+    use crate::cargo_hold::HoldError;
+    use crate::economy;
+    use crate::entity::{Entity, EntityClass};
+    use crate::entity_list::EntityList;
+    use crate::entity_maker;
+    use crate::file_error::FileError;
+    use crate::game_state::{GameState, SaveError, SessionState};
+    use crate::item_id::ItemId;
+    use crate::item_meta::ILM;
+    use crate::jump_drive::Jump;
+    use crate::market;
+    use crate::pos::Position;
+    use crate::route::{self, Route, RouteHop, RouteMode};
+    use crate::univ::Univ;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha12Rng;
+    use serde::{Deserialize, Serialize};
+
+    // Why a GM action was refused, with the numbers behind it
+    // A refused action changes nothing unless the variant says otherwise
+    #[derive(Debug, Clone, Serialize)]
+    #[serde(tag = "kind")]
+    pub enum GMError {
+        // Buyer and seller are the same entity
+        SelfTrade {
+            ent_id: i32,
+        },
+        UnknownEntity {
+            ent_id: i32,
+        },
+        NoDock {
+            ent_id: i32,
+        },
+        TooFar {
+            distance: i32,
+            max: i32,
+        },
+        // `ent_id` has to be docked, to `station_id` if there is one
+        NotDocked {
+            ent_id: i32,
+            station_id: Option<i32>,
+        },
+        Docked {
+            station_id: i32,
+        },
+        OutOfRange {
+            distance: i32,
+            max_range: i32,
+        },
+        NotEnoughFuel {
+            needed: i32,
+            have: i32,
+        },
+        TankFull,
+        NoRoute {
+            target_id: i32,
+        },
+        // The route exists but its fuel costs more than the ship has
+        RouteUnaffordable {
+            route: Box<Route>,
+            have: i32,
+        },
+        InvalidQuantity {
+            qty: i32,
+        },
+        InsufficientCredits {
+            needed: i32,
+            have: i32,
+        },
+        InsufficientStock {
+            item: ItemId,
+            qty: i32,
+            have: i32,
+        },
+        HoldFull {
+            item: ItemId,
+            qty: i32,
+            room: i32,
+        },
+        NotTraded {
+            ent_id: i32,
+            item: ItemId,
+        },
+        // Travel gave up part way, the legs flown so far stay flown
+        Stopped {
+            at: i32,
+            legs: Vec<TravelLeg>,
+            reason: Box<GMError>,
+        },
+        // Saving or loading a game failed, nothing was replaced
+        SaveFile {
+            error: SaveError,
+        },
+    }
+    impl GMError {
+        // Variant name, what --json and the server report as the status
+        pub fn kind(&self) -> &'static str {
+            match self {
+                GMError::SelfTrade { .. } => "SelfTrade",
+                GMError::UnknownEntity { .. } => "UnknownEntity",
+                GMError::NoDock { .. } => "NoDock",
+                GMError::TooFar { .. } => "TooFar",
+                GMError::NotDocked { .. } => "NotDocked",
+                GMError::Docked { .. } => "Docked",
+                GMError::OutOfRange { .. } => "OutOfRange",
+                GMError::NotEnoughFuel { .. } => "NotEnoughFuel",
+                GMError::TankFull => "TankFull",
+                GMError::NoRoute { .. } => "NoRoute",
+                GMError::RouteUnaffordable { .. } => "RouteUnaffordable",
+                GMError::InvalidQuantity { .. } => "InvalidQuantity",
+                GMError::InsufficientCredits { .. } => "InsufficientCredits",
+                GMError::InsufficientStock { .. } => "InsufficientStock",
+                GMError::HoldFull { .. } => "HoldFull",
+                GMError::NotTraded { .. } => "NotTraded",
+                GMError::Stopped { .. } => "Stopped",
+                GMError::SaveFile { .. } => "SaveFile",
+            }
+        }
+    }
+    impl std::fmt::Display for GMError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let sname = |item: &ItemId| ILM.get(item).map_or("?", |meta| meta.sname.as_str());
+            match self {
+                GMError::SelfTrade { ent_id } => {
+                    write!(f, "Entity ID {} cannot trade with itself.", ent_id)
+                }
+                GMError::UnknownEntity { ent_id } => {
+                    write!(f, "No entity found with ID {}.", ent_id)
+                }
+                GMError::NoDock { ent_id } => write!(
+                    f,
+                    "Entity ID {} does not have docking capabilities.",
+                    ent_id
+                ),
+                GMError::TooFar { distance, max } => {
+                    write!(
+                        f,
+                        "Too far away: {} ly, must be within {} ly.",
+                        distance, max
+                    )
+                }
+                GMError::NotDocked {
+                    ent_id,
+                    station_id: Some(station_id),
+                } => write!(
+                    f,
+                    "Entity ID {} is not docked to entity ID {}.",
+                    ent_id, station_id
+                ),
+                GMError::NotDocked { ent_id, .. } => {
+                    write!(f, "Entity ID {} must be docked.", ent_id)
+                }
+                GMError::Docked { station_id } => {
+                    write!(f, "Cannot jump while docked to entity ID {}.", station_id)
+                }
+                GMError::OutOfRange {
+                    distance,
+                    max_range,
+                } => write!(f, "Distance {} exceeds max range {}.", distance, max_range),
+                GMError::NotEnoughFuel { needed, have } => {
+                    write!(f, "Not enough fuel: need {}, have {}.", needed, have)
+                }
+                GMError::TankFull => write!(f, "Jump drive is already full."),
+                GMError::NoRoute { target_id } => {
+                    write!(f, "No chain of jumps reaches entity ID {}.", target_id)
+                }
+                GMError::RouteUnaffordable { route, have } => write!(
+                    f,
+                    "Route needs {} credits of fuel, have {}.",
+                    route.cost, have
+                ),
+                GMError::InvalidQuantity { qty } => write!(f, "Invalid quantity {}.", qty),
+                GMError::InsufficientCredits { needed, have } => {
+                    write!(f, "Not enough credits: need {}, have {}.", needed, have)
+                }
+                GMError::InsufficientStock { item, qty, have } => write!(
+                    f,
+                    "Not enough {}: wanted {}, only {} available.",
+                    sname(item),
+                    qty,
+                    have
+                ),
+                GMError::HoldFull { item, qty, room } => write!(
+                    f,
+                    "No room for {} {}, space for {}.",
+                    qty,
+                    sname(item),
+                    room
+                ),
+                GMError::NotTraded { ent_id, item } => {
+                    write!(f, "Entity ID {} does not deal in {}.", ent_id, sname(item))
+                }
+                GMError::Stopped { at, reason, .. } => {
+                    write!(f, "Stopped before entity ID {}: {}", at, reason)
+                }
+                GMError::SaveFile { error } => write!(f, "{}", error),
+            }
+        }
+    }
+    impl std::error::Error for GMError {}
+    impl From<SaveError> for GMError {
+        fn from(error: SaveError) -> Self {
+            GMError::SaveFile { error }
+        }
+    }
+    impl From<FileError> for GMError {
+        fn from(e: FileError) -> Self {
+            SaveError::Io(e).into()
+        }
+    }
+
+    // Whether a jump would work, and the fuel it would take
+    #[derive(Debug, Serialize)]
+    pub struct JumpCheck {
+        pub distance: i32,
+        pub fuel_needed: i32,
+        pub fuel_cur: i32,
+        pub fuel_after: i32,
+        pub can_jump: bool,
+        // What stops the jump when it can't be made
+        pub blocked: Option<GMError>,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct Refuel {
+        pub amount: i32,
+        pub cost: i32,
+        // Less than asked for when the credits ran short
+        pub wanted: i32,
+    }
+
+    #[derive(Debug, Clone, Serialize)]
+    pub struct TravelLeg {
+        pub ent_id: i32,
+        pub distance: i32,
+        pub fuel_used: i32,
+        pub refuel: i32,
+        pub refuel_cost: i32,
+    }
+
+    // Buy and sell share the same result shape
+    #[derive(Debug, Serialize)]
+    pub struct Trade {
+        pub qty: i32,
+        pub price: i32,
+        pub total: i32,
+    }
+
+    // Fields are only changed by GM actions, frontends read them through getters
+    #[derive(Serialize, Deserialize)]
+    pub struct GM {
+        pub(crate) tick: i32,
+        // Seed the galaxy was generated from, the only copy of it
+        // None for games migrated from saves that never recorded one
+        #[serde(default)]
+        pub(crate) seed: Option<u64>,
+        // The only source of randomness in the game
+        // Everything random should draw from this so a seed is reproducible
+        // Saved with the game so a loaded game keeps drawing the same numbers
+        pub(crate) rng: ChaCha12Rng,
+        // Games saved before this was recorded used the defaults
+        #[serde(default)]
+        pub(crate) univ: Univ,
+    }
+    impl GM {
+        pub fn new(seed: u64, univ: Univ) -> Self {
+            GM {
+                tick: 0,
+                seed: Some(seed),
+                rng: ChaCha12Rng::seed_from_u64(seed),
+                univ,
+            }
+        }
+        pub fn tick(&self) -> i32 {
+            self.tick
+        }
+        pub fn seed(&self) -> Option<u64> {
+            self.seed
+        }
+        pub fn univ(&self) -> &Univ {
+            &self.univ
+        }
+
+        // Fill the galaxy with stations drawn from the game RNG
+        pub fn generate_galaxy(&mut self, entities: &mut EntityList, name_list: &[String]) {
+            let count = self.univ.starting_entities as usize;
+            entities.generate_entities(name_list, &self.univ, count, &mut self.rng);
+        }
+
+        // Player ship in the middle of a freshly generated galaxy
+        pub fn new_galaxy(&mut self, entities: &mut EntityList, name_list: &[String]) {
+            let mut start_ship = entity_maker::ship("Ferris 1", &self.univ);
+            // Starting cargo, anything the catalogue doesn't have or can't fit is left out
+            for (sname, qty) in [("mtll", 20), ("cmpm", 15), ("plyh", 5)] {
+                if let Some(item) = ILM.id_by_sname(sname) {
+                    let _ = start_ship.hold.insert(item, qty);
+                }
+            }
+            let gal_center = self.univ.gal_size / 2;
+            start_ship.set_pos(Position::new(gal_center, gal_center));
+            entities.add(start_ship);
+            self.generate_galaxy(entities, name_list);
+        }
+        pub fn set_target(&self, entities: &mut EntityList, ent_id: i32) -> Result<(), GMError> {
+            if entities.get_by_id(ent_id).is_none() {
+                return Err(GMError::UnknownEntity { ent_id });
+            }
+            entities.get_player_mut().unwrap().targeting_id = Some(ent_id);
+            Ok(())
+        }
+
+        pub fn jump_check(&self, player: &Entity, target: &Position) -> JumpCheck {
+            let distance = player.pos.distance(target);
+            let fuel_needed = player.jump_drive.calc_fuel(distance);
+            let fuel_cur = player.jump_drive.fuel_cur;
+            let blocked = match player.docked_id {
+                Some(station_id) => Some(GMError::Docked { station_id }),
+                None => player.jump_drive.check(distance).err(),
+            };
+            JumpCheck {
+                distance,
+                fuel_needed,
+                fuel_cur,
+                fuel_after: fuel_cur.saturating_sub(fuel_needed),
+                can_jump: blocked.is_none(),
+                blocked,
+            }
+        }
+
+        pub fn jump(
+            &mut self,
+            entities: &mut EntityList,
+            ent_id: i32,
+            destination: &Position,
+        ) -> Result<Jump, GMError> {
+            let jump = entities.jump(ent_id, destination)?;
+            // Time only passes when the ship actually went somewhere
+            self.advance(entities, 1);
+            Ok(jump)
+        }
+
+        // Move the clock forward, running the economy once per tick
+        pub fn advance(&mut self, entities: &mut EntityList, ticks: i32) {
+            for _ in 0..ticks {
+                self.tick += 1;
+                for ent in entities.iter_mut() {
+                    economy::step(ent);
+                }
+            }
+        }
+
+        // Chain of jumps to a far entity, refueling at stations on the way
+        pub fn plan_route(
+            &self,
+            entities: &EntityList,
+            ent_id: i32,
+            target_id: i32,
+            mode: RouteMode,
+        ) -> Result<Route, GMError> {
+            let route = self.find_route(entities, ent_id, target_id, mode)?;
+            let have = entities.get_by_id(ent_id).unwrap().fin.credits;
+            if route.cost > have {
+                return Err(GMError::RouteUnaffordable {
+                    route: Box::new(route),
+                    have,
+                });
+            }
+            Ok(route)
+        }
+
+        // Any route at all, whether or not the fuel is affordable
+        fn find_route(
+            &self,
+            entities: &EntityList,
+            ent_id: i32,
+            target_id: i32,
+            mode: RouteMode,
+        ) -> Result<Route, GMError> {
+            let ship = entities
+                .get_by_id(ent_id)
+                .ok_or(GMError::UnknownEntity { ent_id })?;
+            if entities.get_by_id(target_id).is_none() {
+                return Err(GMError::UnknownEntity { ent_id: target_id });
+            }
+            route::plan(entities, &self.univ, ship, target_id, mode)
+                .ok_or(GMError::NoRoute { target_id })
+        }
+
+        pub fn dock_list(&self, player: &Entity, ent_list: &EntityList) -> Vec<Entity> {
+            ent_list
+                .list_by_distance(player.pos, 1)
+                .into_iter()
+                .filter(|ent| ent.flags.has_dock)
+                .cloned()
+                .collect()
+        }
+
+        pub fn dock(&self, ent_list: &mut EntityList, ent_id: i32) -> Result<(), GMError> {
+            let target = ent_list
+                .get_by_id(ent_id)
+                .ok_or(GMError::UnknownEntity { ent_id })?;
+            if !target.flags.has_dock {
+                return Err(GMError::NoDock { ent_id });
+            }
+            // Copy the position to end the immutable borrow
+            let target_pos = target.pos;
+
+            let ship = ent_list.get_player_mut().unwrap();
+            let distance = ship.pos.distance(&target_pos);
+            if distance > 1 {
+                return Err(GMError::TooFar { distance, max: 1 });
+            }
+            ship.docked_id = Some(ent_id);
+            Ok(())
+        }
+
+        // Returns the station that was left
+        pub fn undock(&self, entities: &mut EntityList, ent_id: i32) -> Result<i32, GMError> {
+            let ent = entities
+                .get_by_id_mut(ent_id)
+                .ok_or(GMError::UnknownEntity { ent_id })?;
+            self.undock_ent(ent)
+        }
+
+        fn undock_ent(&self, ent: &mut Entity) -> Result<i32, GMError> {
+            let station_id = ent.docked_id.take().ok_or(GMError::NotDocked {
+                ent_id: ent.id,
+                station_id: None,
+            })?;
+            Ok(station_id)
+        }
+
+        // Buy up to `amount` fuel while docked
+        // Buys as much as the credits allow if the full amount is too expensive
+        pub fn refuel(
+            &self,
+            entities: &mut EntityList,
+            ent_id: i32,
+            amount: i32,
+        ) -> Result<Refuel, GMError> {
+            let ent = entities
+                .get_by_id_mut(ent_id)
+                .ok_or(GMError::UnknownEntity { ent_id })?;
+            self.refuel_ent(ent, amount)
+        }
+
+        fn refuel_ent(&self, ent: &mut Entity, amount: i32) -> Result<Refuel, GMError> {
+            if amount <= 0 {
+                return Err(GMError::InvalidQuantity { qty: amount });
+            }
+            if ent.docked_id.is_none() {
+                return Err(GMError::NotDocked {
+                    ent_id: ent.id,
+                    station_id: None,
+                });
+            }
+            let wanted = amount.min(ent.jump_drive.refuel_amt());
+            if wanted <= 0 {
+                return Err(GMError::TankFull);
+            }
+            let mut amount = wanted;
+            let mut cost = self.univ.fuel_cost(amount);
+            if ent.fin.credits < cost {
+                amount = self.univ.fuel_for(ent.fin.credits);
+                if amount <= 0 {
+                    return Err(GMError::InsufficientCredits {
+                        needed: cost,
+                        have: ent.fin.credits,
+                    });
+                }
+                cost = self.univ.fuel_cost(amount);
+            }
+            ent.fin.credits -= cost;
+            ent.jump_drive.refuel(amount);
+            Ok(Refuel {
+                amount,
+                cost,
+                wanted,
+            })
+        }
+
+        // Fly the player along a planned route
+        // Undock, refuel if the next leg needs it, jump, dock, repeat
+        // Goes ahead even if the fuel looks unaffordable, stopping when it runs out
+        pub fn travel(
+            &mut self,
+            entities: &mut EntityList,
+            target_id: i32,
+            mode: RouteMode,
+        ) -> Result<Vec<TravelLeg>, GMError> {
+            let player_id = entities.get_player().unwrap().id;
+            let route = self.find_route(entities, player_id, target_id, mode)?;
+            let mut legs = Vec::new();
+            for hop in route.hops.iter() {
+                if let Err(reason) = self.travel_leg(entities, target_id, hop, &mut legs) {
+                    return Err(GMError::Stopped {
+                        at: hop.ent_id,
+                        legs,
+                        reason: Box::new(reason),
+                    });
+                }
+            }
+            Ok(legs)
+        }
+
+        // One hop of `travel`, the leg is recorded once anything has happened
+        fn travel_leg(
+            &mut self,
+            entities: &mut EntityList,
+            target_id: i32,
+            hop: &RouteHop,
+            legs: &mut Vec<TravelLeg>,
+        ) -> Result<(), GMError> {
+            let player_id = entities.get_player().unwrap().id;
+            let mut leg = TravelLeg {
+                ent_id: hop.ent_id,
+                distance: 0,
+                fuel_used: 0,
+                refuel: 0,
+                refuel_cost: 0,
+            };
+            let ship = entities.get_player_mut().unwrap();
+            let leg_fuel = ship.jump_drive.calc_fuel(ship.pos.distance(&hop.pos));
+            if ship.jump_drive.fuel_cur < leg_fuel && ship.docked_id.is_some() {
+                let amount = ship.jump_drive.refuel_amt();
+                if let Ok(refuel) = self.refuel_ent(ship, amount) {
+                    leg.refuel = refuel.amount;
+                    leg.refuel_cost = refuel.cost;
+                }
+                if ship.jump_drive.fuel_cur < leg_fuel {
+                    let needed = self.univ.fuel_cost(leg_fuel - ship.jump_drive.fuel_cur);
+                    let have = ship.fin.credits;
+                    legs.push(leg);
+                    return Err(GMError::InsufficientCredits { needed, have });
+                }
+            }
+            let _ = self.undock_ent(ship);
+
+            let jump = self.jump(entities, player_id, &hop.pos);
+            if let Ok(jump) = &jump {
+                leg.distance = jump.distance;
+                leg.fuel_used = jump.fuel_used;
+            }
+            legs.push(leg);
+            jump?;
+
+            let has_dock = entities
+                .get_by_id(hop.ent_id)
+                .is_some_and(|ent| ent.flags.has_dock);
+            // Only the final target may lack a dock
+            if !has_dock && hop.ent_id != target_id {
+                return Err(GMError::NoDock { ent_id: hop.ent_id });
+            }
+            if has_dock {
+                self.dock(entities, hop.ent_id)?;
+            }
+            Ok(())
+        }
+
+        pub fn name_ent(
+            &self,
+            entities: &mut EntityList,
+            ent_id: i32,
+            new_name: &str,
+        ) -> Result<(), GMError> {
+            let ent = entities
+                .get_by_id_mut(ent_id)
+                .ok_or(GMError::UnknownEntity { ent_id })?;
+            ent.name = new_name.to_string();
+            Ok(())
+        }
+
+        // Buy from another entity at the seller's price
+        pub fn buy(
+            &self,
+            entities: &mut EntityList,
+            buyer_id: i32,
+            seller_id: i32,
+            item: &ItemId,
+            qty: i32,
+        ) -> Result<Trade, GMError> {
+            self.trade(entities, buyer_id, seller_id, seller_id, item, qty)
+        }
+
+        // Sell to another entity at the buyer's price
+        // The buyer pays out of its own credits
+        pub fn sell(
+            &self,
+            entities: &mut EntityList,
+            seller_id: i32,
+            buyer_id: i32,
+            item: &ItemId,
+            qty: i32,
+        ) -> Result<Trade, GMError> {
+            self.trade(entities, buyer_id, seller_id, buyer_id, item, qty)
+        }
+
+        // Move goods from seller to buyer at the price listed by `quote_id`
+        // All checks happen before anything is modified
+        // A failed trade leaves both entities untouched
+        fn trade(
+            &self,
+            entities: &mut EntityList,
+            buyer_id: i32,
+            seller_id: i32,
+            quote_id: i32,
+            item: &ItemId,
+            qty: i32,
+        ) -> Result<Trade, GMError> {
+            if buyer_id == seller_id {
+                return Err(GMError::SelfTrade { ent_id: buyer_id });
+            }
+            let buyer = entities
+                .get_by_id(buyer_id)
+                .ok_or(GMError::UnknownEntity { ent_id: buyer_id })?;
+            let seller = entities
+                .get_by_id(seller_id)
+                .ok_or(GMError::UnknownEntity { ent_id: seller_id })?;
+            if qty <= 0 {
+                return Err(GMError::InvalidQuantity { qty });
+            }
+            // Must be at same position to trade
+            let distance = buyer.pos.distance(&seller.pos);
+            if distance > 0 {
+                return Err(GMError::TooFar { distance, max: 0 });
+            }
+            // Station require docking to trade
+            if seller.class == EntityClass::Station && buyer.docked_id != Some(seller.id) {
+                return Err(GMError::NotDocked {
+                    ent_id: buyer.id,
+                    station_id: Some(seller.id),
+                });
+            }
+            if buyer.class == EntityClass::Station && seller.docked_id != Some(buyer.id) {
+                return Err(GMError::NotDocked {
+                    ent_id: seller.id,
+                    station_id: Some(buyer.id),
+                });
+            }
+            let quote = if quote_id == seller_id { seller } else { buyer };
+            if !market::deals_in(quote, item) {
+                return Err(GMError::NotTraded {
+                    ent_id: quote.id,
+                    item: *item,
+                });
+            }
+            if let Err(HoldError::NotEnough { have }) = seller.hold.check_remove(item, qty) {
+                return Err(GMError::InsufficientStock {
+                    item: *item,
+                    qty,
+                    have,
+                });
+            }
+            if buyer.hold.check_insert(item, qty).is_err() {
+                return Err(GMError::HoldFull {
+                    item: *item,
+                    qty,
+                    room: buyer.hold.max_insertable(item),
+                });
+            }
+
+            // Stations price every piece at the stock level it leaves behind
+            let total = market::trade_total(quote, item, qty, quote_id == seller_id);
+            let price = total / qty;
+            if buyer.fin.credits < total {
+                return Err(GMError::InsufficientCredits {
+                    needed: total,
+                    have: buyer.fin.credits,
+                });
+            }
+
+            // Both holds were checked above so these cannot fail
+            let seller = entities.get_by_id_mut(seller_id).unwrap();
+            seller.fin.credits += total;
+            seller.hold.remove(*item, qty).unwrap();
+            market::reprice_item(seller, item);
+            let buyer = entities.get_by_id_mut(buyer_id).unwrap();
+            buyer.fin.credits -= total;
+            buyer.hold.insert(*item, qty).unwrap();
+            market::reprice_item(buyer, item);
+            Ok(Trade { qty, price, total })
+        }
+
+        // Returns where the game went
+        pub fn save(
+            &self,
+            entities: &EntityList,
+            session: &SessionState,
+            filename: &str,
+        ) -> Result<String, GMError> {
+            // Serialize the whole game to JSON and save to file
+            let serialized = GameState::to_json(self, session, entities)
+                .map_err(|e| SaveError::Parse(e.to_string()))?;
+            // Write to a temp file first so a failed write can't clobber an old save
+            let tmp = format!("{}.tmp", filename);
+            std::fs::write(&tmp, serialized).map_err(|e| FileError::io(filename, e))?;
+            std::fs::rename(&tmp, filename).map_err(|e| {
+                let _ = std::fs::remove_file(&tmp);
+                FileError::io(filename, e)
+            })?;
+            Ok(filename.to_string())
+        }
+
+        pub fn load(
+            &mut self,
+            entities: &mut EntityList,
+            session: &mut SessionState,
+            filename: &str,
+        ) -> Result<String, GMError> {
+            // Load the game from JSON file, migrating older saves
+            // Nothing is replaced unless the whole file checks out
+            let data = FileError::read(filename)?;
+            let state = GameState::from_json(&data)?;
+            *self = state.gm;
+            *session = state.session;
+            *entities = state.entities;
+            Ok(filename.to_string())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn galaxy(seed: u64) -> EntityList {
+            let names: Vec<String> = ["Alpha", "Beta", "Gamma", "Delta"]
+                .map(String::from)
+                .to_vec();
+            let mut gm = GM::new(seed, Univ::default());
+            let mut entities = EntityList::new();
+            gm.new_galaxy(&mut entities, &names);
+            entities
+        }
+
+        #[test]
+        fn same_seed_same_galaxy() {
+            let first = galaxy(42);
+            // The ship plus every station
+            assert_eq!(first.list().len(), 10001);
+            let first = serde_json::to_string(&first).unwrap();
+            let second = serde_json::to_string(&galaxy(42)).unwrap();
+            assert_eq!(first, second);
+        }
+
+        #[test]
+        fn different_seed_different_galaxy() {
+            let first = serde_json::to_string(&galaxy(42)).unwrap();
+            let second = serde_json::to_string(&galaxy(43)).unwrap();
+            assert_ne!(first, second);
+        }
+
+        #[test]
+        fn failed_jump_takes_no_time() {
+            let mut gm = GM::new(1, Univ::default());
+            let mut entities = EntityList::new();
+            entities.add(crate::entity_maker::ship("Ship", &gm.univ));
+            let far = Position::new(5000, 0);
+            assert!(gm.jump(&mut entities, 0, &far).is_err());
+            assert_eq!(gm.tick, 0);
+            gm.jump(&mut entities, 0, &Position::new(50, 0)).unwrap();
+            assert_eq!(gm.tick, 1);
+        }
+
+        #[test]
+        fn refuel_rejects_bad_amounts_before_the_tank() {
+            let gm = GM::new(1, Univ::default());
+            let (mut entities, _) = docked_at_depot(&gm);
+            // Full tank, a bad amount is still reported as bad
+            for amount in [0, -5] {
+                assert!(matches!(
+                    gm.refuel(&mut entities, 0, amount),
+                    Err(GMError::InvalidQuantity { qty }) if qty == amount
+                ));
+            }
+            assert!(matches!(
+                gm.refuel(&mut entities, 0, 5),
+                Err(GMError::TankFull)
+            ));
+        }
+
+        // The player's ship docked at a station that deals in one item
+        fn docked_at_depot(gm: &GM) -> (EntityList, ItemId) {
+            let item = ILM.ids().next().unwrap();
+            let mut entities = EntityList::new();
+            let mut ship = crate::entity_maker::ship("Ship", &gm.univ);
+            ship.docked_id = Some(1);
+            entities.add(ship);
+            let mut station = Entity::new("Depot");
+            station.class = EntityClass::Station;
+            station.flags.has_dock = true;
+            station.fin.credits = gm.univ.station_credits;
+            station.hold = crate::cargo_hold::CargoHold::new(gm.univ.station_hold);
+            station.fin.stock_target.set(item, 100);
+            station.hold.insert(item, 100).unwrap();
+            market::reprice(&mut station);
+            entities.add(station);
+            (entities, item)
+        }
+
+        fn snapshot(entities: &EntityList) -> String {
+            serde_json::to_string(entities).unwrap()
+        }
+
+        #[test]
+        fn buy_moves_credits_and_goods() {
+            let gm = GM::new(1, Univ::default());
+            let (mut entities, item) = docked_at_depot(&gm);
+            let total = market::trade_total(entities.get_by_id(1).unwrap(), &item, 5, true);
+            let trade = gm.buy(&mut entities, 0, 1, &item, 5).unwrap();
+            assert_eq!(trade.total, total);
+            let ship = entities.get_by_id(0).unwrap();
+            assert_eq!(ship.fin.credits, gm.univ.start_credits - total);
+            assert_eq!(ship.hold.qty(&item), 5);
+            let station = entities.get_by_id(1).unwrap();
+            assert_eq!(station.fin.credits, gm.univ.station_credits + total);
+            assert_eq!(station.hold.qty(&item), 95);
+        }
+
+        #[test]
+        fn sell_moves_credits_and_goods() {
+            let gm = GM::new(1, Univ::default());
+            let (mut entities, item) = docked_at_depot(&gm);
+            entities
+                .get_by_id_mut(0)
+                .unwrap()
+                .hold
+                .insert(item, 5)
+                .unwrap();
+            let total = market::trade_total(entities.get_by_id(1).unwrap(), &item, 5, false);
+            let trade = gm.sell(&mut entities, 0, 1, &item, 5).unwrap();
+            assert_eq!(trade.total, total);
+            let ship = entities.get_by_id(0).unwrap();
+            assert_eq!(ship.fin.credits, gm.univ.start_credits + total);
+            assert_eq!(ship.hold.qty(&item), 0);
+            let station = entities.get_by_id(1).unwrap();
+            assert_eq!(station.fin.credits, gm.univ.station_credits - total);
+            assert_eq!(station.hold.qty(&item), 105);
+        }
+
+        #[test]
+        fn buy_at_ask_sell_at_bid() {
+            let gm = GM::new(1, Univ::default());
+            let (mut entities, item) = docked_at_depot(&gm);
+            let station = entities.get_by_id(1).unwrap();
+            let (ask, bid) = (station.fin.ask(&item), station.fin.bid(&item));
+            assert!(ask > bid);
+            assert_eq!(gm.buy(&mut entities, 0, 1, &item, 1).unwrap().price, ask);
+
+            let (mut entities, item) = docked_at_depot(&gm);
+            entities
+                .get_by_id_mut(0)
+                .unwrap()
+                .hold
+                .insert(item, 1)
+                .unwrap();
+            assert_eq!(gm.sell(&mut entities, 0, 1, &item, 1).unwrap().price, bid);
+        }
+
+        #[test]
+        fn failed_trades_change_nothing() {
+            let gm = GM::new(1, Univ::default());
+            let (mut entities, item) = docked_at_depot(&gm);
+            entities.get_by_id_mut(0).unwrap().fin.credits = 1;
+            let before = snapshot(&entities);
+            assert!(matches!(
+                gm.buy(&mut entities, 0, 1, &item, 5),
+                Err(GMError::InsufficientCredits { .. })
+            ));
+            assert!(matches!(
+                gm.buy(&mut entities, 0, 1, &item, 101),
+                Err(GMError::InsufficientStock { have: 100, .. })
+            ));
+            assert!(matches!(
+                gm.sell(&mut entities, 0, 1, &item, 1),
+                Err(GMError::InsufficientStock { have: 0, .. })
+            ));
+            assert_eq!(snapshot(&entities), before);
+
+            let (mut entities, item) = docked_at_depot(&gm);
+            entities.get_by_id_mut(0).unwrap().hold.vol_max = 0;
+            let before = snapshot(&entities);
+            assert!(matches!(
+                gm.buy(&mut entities, 0, 1, &item, 1),
+                Err(GMError::HoldFull { room: 0, .. })
+            ));
+            assert_eq!(snapshot(&entities), before);
+
+            let (mut entities, item) = docked_at_depot(&gm);
+            entities
+                .get_by_id_mut(0)
+                .unwrap()
+                .hold
+                .insert(item, 5)
+                .unwrap();
+            entities.get_by_id_mut(1).unwrap().fin.credits = 0;
+            let before = snapshot(&entities);
+            assert!(matches!(
+                gm.sell(&mut entities, 0, 1, &item, 5),
+                Err(GMError::InsufficientCredits { have: 0, .. })
+            ));
+            assert_eq!(snapshot(&entities), before);
+        }
+    }
+}
+
+// Everything that has to survive a save and load
+pub mod game_state {
+    use crate::entity::EntityClass;
+    use crate::entity_list::EntityList;
+    use crate::entity_maker;
+    use crate::file_error::FileError;
+    use crate::gm::GM;
+    use crate::item_meta::ILM;
+    use crate::market;
+    use crate::univ::Univ;
+    use serde::{Deserialize, Serialize};
+
+    // Bump when the save layout changes and add a migration below
+    // 0: bare EntityList, before GameState existed
+    // 1: GameState with gm and session
+    // 2: adds meta, read by the save slot list
+    // 3: items keyed by short name, older names are mapped by ItemId
+    // 4: gm records the universe settings
+    // 5: every station has a type and stock targets
+    pub const SAVE_VERSION: u32 = 5;
+
+    #[derive(Debug, Clone, Serialize)]
+    #[serde(tag = "reason", content = "detail")]
+    pub enum SaveError {
+        // Reading or writing the file failed
+        Io(FileError),
+        // The file isn't valid JSON or doesn't match the save layout
+        Parse(String),
+        // The file parsed but the game inside it is broken
+        Invalid(String),
+        // Saved by a newer version of the game
+        UnsupportedVersion(u32),
+    }
+    impl From<FileError> for SaveError {
+        fn from(e: FileError) -> Self {
+            SaveError::Io(e)
+        }
+    }
+    impl std::error::Error for SaveError {}
+    impl std::fmt::Display for SaveError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                SaveError::Io(e) => write!(f, "{}", e),
+                SaveError::Parse(msg) => write!(f, "corrupt save file: {}", msg),
+                SaveError::Invalid(msg) => write!(f, "invalid save file: {}", msg),
+                SaveError::UnsupportedVersion(v) => write!(
+                    f,
+                    "save version {} is newer than supported version {}",
+                    v, SAVE_VERSION
+                ),
+            }
+        }
+    }
+
+    // Summary shown in the save slot list
+    #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+    pub struct SaveMeta {
+        pub ship_name: String,
+        pub credits: i32,
+        pub tick: i32,
+        // Real time, seconds since the Unix epoch
+        pub saved_at: u64,
+    }
+    impl SaveMeta {
+        pub fn new(gm: &GM, entities: &EntityList) -> Self {
+            let (ship_name, credits) = match entities.get_player() {
+                Some(ship) => (ship.name.clone(), ship.fin.credits),
+                None => (String::new(), 0),
+            };
+            let saved_at = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+            SaveMeta {
+                ship_name,
+                credits,
+                tick: gm.tick,
+                saved_at,
+            }
+        }
+    }
+
+    // Per-player state that isn't part of the simulation
+    #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+    pub struct SessionState {
+        pub last_id: i32,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct GameState {
+        pub version: u32,
+        #[serde(default)]
+        pub meta: SaveMeta,
+        pub gm: GM,
+        pub session: SessionState,
+        pub entities: EntityList,
+    }
+
+    // Borrowed view used for saving, so nothing has to be cloned
+    #[derive(Serialize)]
+    struct GameStateRef<'a> {
+        version: u32,
+        meta: SaveMeta,
+        gm: &'a GM,
+        session: &'a SessionState,
+        entities: &'a EntityList,
+    }
+
+    impl GameState {
+        pub fn to_json(
+            gm: &GM,
+            session: &SessionState,
+            entities: &EntityList,
+        ) -> Result<String, serde_json::Error> {
+            serde_json::to_string_pretty(&GameStateRef {
+                version: SAVE_VERSION,
+                meta: SaveMeta::new(gm, entities),
+                gm,
+                session,
+                entities,
+            })
+        }
+
+        // Parse a save of any known version and bring it up to date
+        pub fn from_json(data: &str) -> Result<GameState, SaveError> {
+            let parse_err = |e: serde_json::Error| SaveError::Parse(e.to_string());
+            let value: serde_json::Value = serde_json::from_str(data).map_err(parse_err)?;
+            let version = value.get("version").and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+            if version > SAVE_VERSION {
+                return Err(SaveError::UnsupportedVersion(version));
+            }
+            let mut state = if version == 0 {
+                GameState::migrate_v0(serde_json::from_value(value).map_err(parse_err)?)
+            } else {
+                serde_json::from_value::<GameState>(value).map_err(parse_err)?
+            };
+            state.entities.validate().map_err(SaveError::Invalid)?;
+            state
+                .gm
+                .univ
+                .validate()
+                .map_err(|e| SaveError::Invalid(e.to_string()))?;
+            if version < 5 {
+                GameState::migrate_v4(&mut state);
+            }
+            state.version = SAVE_VERSION;
+            state.entities.reindex();
+            Ok(state)
+        }
+
+        // Old saves only stored the entity list, the galaxy seed was never saved
+        // The seed stays unknown and the clock starts over
+        // The RNG starts from a fixed seed so migrating a save is repeatable
+        fn migrate_v0(entities: EntityList) -> GameState {
+            let mut gm = GM::new(0, Univ::default());
+            gm.seed = None;
+            GameState {
+                version: 0,
+                meta: SaveMeta::default(),
+                gm,
+                session: SessionState::default(),
+                entities,
+            }
+        }
+
+        // Stations saved before stock targets existed deal in nothing
+        // Each one gets a type and stock drawn from the game RNG
+        // Their holds grow to a station's size so the stock fits
+        fn migrate_v4(state: &mut GameState) {
+            let gm = &mut state.gm;
+            for ent in state.entities.iter_mut() {
+                let stocked = ILM.ids().any(|item| market::deals_in(ent, &item));
+                if ent.class != EntityClass::Station || (stocked && ent.station_type.is_some()) {
+                    continue;
+                }
+                ent.hold.vol_max = ent.hold.vol_max.max(gm.univ.station_hold);
+                entity_maker::fit_out(ent, &gm.univ, &mut gm.rng);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::gm::GMError;
+
+        // Baseline saves were the bare entity list
+        fn v0_save() -> String {
+            let mut entities = EntityList::new();
+            entities.add(crate::entity_maker::ship("Ship", &Univ::default()));
+            serde_json::to_string(&entities).unwrap()
+        }
+
+        fn current_save(gm: &GM) -> serde_json::Value {
+            let mut entities = EntityList::new();
+            entities.add(crate::entity_maker::ship("Ship", &gm.univ));
+            entities.add(crate::entity::Entity::new("Depot"));
+            let json = GameState::to_json(gm, &SessionState::default(), &entities).unwrap();
+            serde_json::from_str(&json).unwrap()
+        }
+
+        fn is_invalid(save: &serde_json::Value) -> bool {
+            matches!(
+                GameState::from_json(&save.to_string()),
+                Err(SaveError::Invalid(_))
+            )
+        }
+
+        #[test]
+        fn from_json_rejects_broken_entities() {
+            let save = current_save(&GM::new(1, Univ::default()));
+            assert!(GameState::from_json(&save.to_string()).is_ok());
+
+            let mut bad_player = save.clone();
+            bad_player["entities"]["entities"][0]["id"] = 1.into();
+            bad_player["entities"]["entities"][1]["id"] = 0.into();
+            assert!(is_invalid(&bad_player));
+
+            let mut duplicate = save.clone();
+            duplicate["entities"]["entities"][1]["id"] = 0.into();
+            assert!(is_invalid(&duplicate));
+
+            let mut id_acc = save.clone();
+            id_acc["entities"]["id_acc"] = 1.into();
+            assert!(is_invalid(&id_acc));
+
+            // Entities that would fail validation in an old bare-list save
+            let mut v0 = save["entities"].clone();
+            v0["id_acc"] = 0.into();
+            assert!(is_invalid(&v0));
+        }
+
+        #[test]
+        fn failed_load_keeps_the_running_game() {
+            let mut gm = GM::new(7, Univ::default());
+            gm.tick = 12;
+            let mut entities = EntityList::new();
+            entities.add(crate::entity_maker::ship("Ship", &gm.univ));
+            let mut session = SessionState { last_id: 3 };
+            let before = GameState::to_json(&gm, &session, &entities).unwrap();
+
+            let mut broken = current_save(&GM::new(8, Univ::default()));
+            broken["entities"]["id_acc"] = 0.into();
+            let path = std::env::temp_dir()
+                .join(format!("spacetraders-broken-{}.json", std::process::id()));
+            std::fs::write(&path, broken.to_string()).unwrap();
+            let res = gm.load(&mut entities, &mut session, &path.to_string_lossy());
+            let _ = std::fs::remove_file(&path);
+            assert!(matches!(
+                res,
+                Err(GMError::SaveFile {
+                    error: SaveError::Invalid(_)
+                })
+            ));
+
+            let after = GameState::to_json(&gm, &session, &entities).unwrap();
+            let strip = |json: &str| {
+                let mut value: serde_json::Value = serde_json::from_str(json).unwrap();
+                // Saved-at time is the only thing allowed to differ
+                value["meta"]["saved_at"] = 0.into();
+                value
+            };
+            assert_eq!(strip(&after), strip(&before));
+        }
+
+        // A save from before stations had stock, `version` None for a bare list
+        fn unstocked_save(version: Option<u32>) -> String {
+            let mut save = current_save(&GM::new(4, Univ::default()));
+            let station = &mut save["entities"]["entities"][1];
+            station["class"] = "Station".into();
+            station["flags"]["has_dock"] = true.into();
+            station["fin"]["credits"] = 10000.into();
+            station["hold"] = serde_json::json!({ "vol_max": 1000, "vol": 0, "inv": {} });
+            let station = station.as_object_mut().unwrap();
+            station.remove("station_type");
+            station["fin"]
+                .as_object_mut()
+                .unwrap()
+                .remove("stock_target");
+            save["entities"]["entities"][0]["docked_id"] = 1.into();
+            match version {
+                Some(version) => {
+                    save["version"] = version.into();
+                    save.to_string()
+                }
+                None => save["entities"].to_string(),
+            }
+        }
+
+        #[test]
+        fn old_stations_are_stocked_and_trade() {
+            for version in [None, Some(1), Some(4)] {
+                let state = GameState::from_json(&unstocked_save(version)).unwrap();
+                let (gm, mut entities) = (state.gm, state.entities);
+                let station = entities.get_by_id(1).unwrap();
+                assert!(station.station_type.is_some());
+                assert!(station.hold.vol <= station.hold.vol_max);
+                let item = ILM
+                    .ids()
+                    .find(|item| station.fin.stock_target.get(item).is_some_and(|t| *t > 0))
+                    .unwrap();
+                let stock = station.hold.qty(&item);
+                assert!(stock > 0, "{:?}", version);
+                gm.buy(&mut entities, 0, 1, &item, 1).unwrap();
+                assert_eq!(entities.get_by_id(1).unwrap().hold.qty(&item), stock - 1);
+                gm.sell(&mut entities, 0, 1, &item, 1).unwrap();
+            }
+        }
+
+        #[test]
+        fn v0_save_has_no_seed() {
+            let state = GameState::from_json(&v0_save()).unwrap();
+            assert_eq!(state.gm.seed, None);
+            assert_eq!(state.gm.tick, 0);
+            assert_eq!(state.version, SAVE_VERSION);
+        }
+    }
+}
+
+// Named save slots in a per-user data directory
+// Built on GM::save and GM::load, this only decides where files go
+pub mod slots {
+    use crate::entity_list::EntityList;
+    use crate::file_error::FileError;
+    use crate::game_state::{SaveError, SaveMeta, SessionState};
+    use crate::gm::{GM, GMError};
+    use serde::Deserialize;
+    use std::path::{Path, PathBuf};
+
+    pub static DEFAULT_SLOT: &str = "quicksave";
+    static AUTOSAVE_PREFIX: &str = "autosave-";
+    // Most autosave files kept in rotation
+    pub const MAX_AUTOSAVE_KEEP: usize = 20;
+
+    pub struct SlotInfo {
+        pub name: String,
+        pub meta: Result<SaveMeta, SaveError>,
+    }
+
+    // Only the header is needed to list slots
+    #[derive(Deserialize)]
+    struct MetaOnly {
+        #[serde(default)]
+        meta: SaveMeta,
+    }
+
+    pub struct SaveSlots {
+        pub dir: PathBuf,
+        // Autosave every N ticks, 0 turns it off
+        pub autosave_every: i32,
+        // How many autosave files to rotate through
+        pub autosave_keep: usize,
+        last_autosave_tick: i32,
+    }
+    impl SaveSlots {
+        pub fn new(dir: PathBuf) -> Self {
+            SaveSlots {
+                dir,
+                autosave_every: 0,
+                autosave_keep: 3,
+                last_autosave_tick: 0,
+            }
+        }
+
+        // Platform data directory, falling back to the working directory
+        pub fn default_dir() -> PathBuf {
+            let env_dir = |key: &str| std::env::var_os(key).filter(|v| !v.is_empty());
+            let base = if cfg!(windows) {
+                env_dir("APPDATA").map(PathBuf::from)
+            } else if cfg!(target_os = "macos") {
+                env_dir("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+            } else {
+                env_dir("XDG_DATA_HOME").map(PathBuf::from).or_else(|| {
+                    env_dir("HOME").map(|home| PathBuf::from(home).join(".local/share"))
+                })
+            };
+            match base {
+                Some(base) => base.join("spacetraders").join("saves"),
+                None => PathBuf::from("saves"),
+            }
+        }
+
+        // Slot names become file names so keep them simple
+        pub fn check_name(slot: &str) -> Result<(), GMError> {
+            let valid = !slot.is_empty()
+                && slot.len() <= 32
+                && slot
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if valid {
+                Ok(())
+            } else {
+                Err(SaveError::Invalid(format!(
+                    "bad slot name '{}', use up to 32 letters, digits, '-' or '_'",
+                    slot
+                ))
+                .into())
+            }
+        }
+
+        pub fn path(&self, slot: &str) -> Result<PathBuf, GMError> {
+            SaveSlots::check_name(slot)?;
+            Ok(self.dir.join(format!("{}.json", slot)))
+        }
+
+        fn ensure_dir(&self) -> Result<(), GMError> {
+            std::fs::create_dir_all(&self.dir)
+                .map_err(|e| FileError::io(&self.dir.to_string_lossy(), e).into())
+        }
+
+        pub fn save(
+            &self,
+            gm: &GM,
+            entities: &EntityList,
+            session: &SessionState,
+            slot: &str,
+        ) -> Result<String, GMError> {
+            let path = self.path(slot)?;
+            self.ensure_dir()?;
+            gm.save(entities, session, &path.to_string_lossy())
+        }
+
+        pub fn load(
+            &self,
+            gm: &mut GM,
+            entities: &mut EntityList,
+            session: &mut SessionState,
+            slot: &str,
+        ) -> Result<String, GMError> {
+            let path = self.path(slot)?;
+            gm.load(entities, session, &path.to_string_lossy())
+        }
+
+        pub fn delete(&self, slot: &str) -> Result<(), GMError> {
+            let path = self.path(slot)?;
+            std::fs::remove_file(&path)
+                .map_err(|e| FileError::io(&path.to_string_lossy(), e).into())
+        }
+
+        // All slots, newest first
+        pub fn list(&self) -> Result<Vec<SlotInfo>, GMError> {
+            let dir = match std::fs::read_dir(&self.dir) {
+                Ok(dir) => dir,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+                Err(e) => return Err(FileError::io(&self.dir.to_string_lossy(), e).into()),
+            };
+            let mut slots = Vec::new();
+            for entry in dir.flatten() {
+                let path = entry.path();
+                if path.extension().is_none_or(|ext| ext != "json") {
+                    continue;
+                }
+                let Some(name) = path.file_stem().map(|n| n.to_string_lossy().to_string()) else {
+                    continue;
+                };
+                slots.push(SlotInfo {
+                    name,
+                    meta: SaveSlots::read_meta(&path),
+                });
+            }
+            slots.sort_by_key(|slot| {
+                let saved_at = slot.meta.as_ref().map_or(0, |meta| meta.saved_at);
+                (std::cmp::Reverse(saved_at), slot.name.clone())
+            });
+            Ok(slots)
+        }
+
+        fn read_meta(path: &Path) -> Result<SaveMeta, SaveError> {
+            let data = std::fs::read_to_string(path)
+                .map_err(|e| FileError::io(&path.to_string_lossy(), e))?;
+            let header: MetaOnly =
+                serde_json::from_str(&data).map_err(|e| SaveError::Parse(e.to_string()))?;
+            Ok(header.meta)
+        }
+
+        // Save into autosave-1 once enough ticks have passed
+        // Older autosaves shift down and the oldest drops off
+        // The new save is written first so a failed write loses nothing
+        pub fn autosave(
+            &mut self,
+            gm: &GM,
+            entities: &EntityList,
+            session: &SessionState,
+        ) -> Option<Result<String, GMError>> {
+            if self.autosave_every <= 0 || gm.tick - self.last_autosave_tick < self.autosave_every {
+                return None;
+            }
+            self.last_autosave_tick = gm.tick;
+            Some(self.rotate_autosave(gm, entities, session))
+        }
+
+        fn rotate_autosave(
+            &self,
+            gm: &GM,
+            entities: &EntityList,
+            session: &SessionState,
+        ) -> Result<String, GMError> {
+            let keep = self.autosave_keep.clamp(1, MAX_AUTOSAVE_KEEP);
+            let slot = |n: usize| self.dir.join(format!("{}{}.json", AUTOSAVE_PREFIX, n));
+            // Not a .json file, so it never shows up as a slot
+            let pending = self.dir.join(format!("{}pending", AUTOSAVE_PREFIX));
+            self.ensure_dir()?;
+            gm.save(entities, session, &pending.to_string_lossy())?;
+            let _ = std::fs::remove_file(slot(keep));
+            for n in (1..keep).rev() {
+                let _ = std::fs::rename(slot(n), slot(n + 1));
+            }
+            let newest = slot(1);
+            std::fs::rename(&pending, &newest)
+                .map_err(|e| FileError::io(&newest.to_string_lossy(), e))?;
+            Ok(newest.to_string_lossy().to_string())
+        }
+
+        // Loading a game resets the autosave clock
+        pub fn reset_autosave(&mut self, tick: i32) {
+            self.last_autosave_tick = tick;
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::entity::Entity;
+        use crate::univ::Univ;
+
+        fn scratch_dir(name: &str) -> PathBuf {
+            let dir =
+                std::env::temp_dir().join(format!("spacetraders-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            dir
+        }
+
+        fn autosave_names(slots: &SaveSlots) -> Vec<String> {
+            let mut names: Vec<String> =
+                slots.list().unwrap().into_iter().map(|s| s.name).collect();
+            names.sort();
+            names
+        }
+
+        #[test]
+        fn autosave_rotates_up_to_keep() {
+            let mut slots = SaveSlots::new(scratch_dir("rotate"));
+            slots.autosave_every = 1;
+            slots.autosave_keep = 2;
+            let mut gm = GM::new(1, Univ::default());
+            let mut entities = EntityList::new();
+            entities.add(Entity::new("Ship"));
+            let session = SessionState::default();
+            for _ in 0..3 {
+                gm.tick += 1;
+                slots.autosave(&gm, &entities, &session).unwrap().unwrap();
+            }
+            assert_eq!(autosave_names(&slots), vec!["autosave-1", "autosave-2"]);
+            let _ = std::fs::remove_dir_all(&slots.dir);
+        }
+
+        #[test]
+        fn failed_autosave_keeps_old_files() {
+            let mut slots = SaveSlots::new(scratch_dir("failed"));
+            slots.autosave_every = 1;
+            slots.autosave_keep = 1;
+            let mut gm = GM::new(1, Univ::default());
+            let mut entities = EntityList::new();
+            entities.add(Entity::new("Ship"));
+            let session = SessionState::default();
+            gm.tick += 1;
+            slots.autosave(&gm, &entities, &session).unwrap().unwrap();
+            let before = std::fs::read_to_string(slots.dir.join("autosave-1.json")).unwrap();
+
+            // A directory where the new save should go makes the write fail
+            std::fs::create_dir(slots.dir.join("autosave-pending")).unwrap();
+            gm.tick += 1;
+            assert!(slots.autosave(&gm, &entities, &session).unwrap().is_err());
+            let after = std::fs::read_to_string(slots.dir.join("autosave-1.json")).unwrap();
+            assert_eq!(before, after);
+            let _ = std::fs::remove_dir_all(&slots.dir);
+        }
+    }
+}
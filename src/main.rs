@@ -1,5 +1,4 @@
-#![allow(unused_variables)]
-#![allow(dead_code)]
+#![allow(clippy::upper_case_acronyms)]
 
 mod ch {
     // TODO:
@@ -8,8 +7,6 @@ mod ch {
     // Sometimes as emojis, sometimes as plain text
     pub static ARL: &str = "⮜";
     pub static ARR: &str = "⮞";
-    pub static ARU: &str = "⮝";
-    pub static ARD: &str = "⮟";
     pub static SP1: &str = "⏣";
     pub static ERR: &str = "✖";
     pub static SUC: &str = "✔";
@@ -35,1103 +32,871 @@ mod fmt {
     pub fn ent_id(ent_id: &i32) -> String {
         format!("#{}", ent_id)
     }
-}
-
-// Universal constants
-mod univ {
-    pub struct Univ {
-        pub gal_size: i32,
-        pub starting_entities: i32,
+    // Unix seconds as a UTC date and time
+    pub fn timestamp(secs: &u64) -> String {
+        let days = (*secs / 86400) as i64;
+        let rem = *secs % 86400;
+        // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+        format!(
+            "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+            year,
+            month,
+            day,
+            rem / 3600,
+            rem % 3600 / 60
+        )
     }
-    pub static UNIV: Univ = Univ {
-        gal_size: 10000,
-        starting_entities: 10000,
-    };
 }
 
-mod pos {
-    use rand::Rng;
-    use serde::{Deserialize, Serialize};
-
-    #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-    pub struct Position {
-        pub x: i32,
-        pub y: i32,
-    }
-    impl std::fmt::Display for Position {
-        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            write!(f, "X{} Y{}", self.x, self.y)
-        }
+mod input {
+    use crate::ch;
+    use crate::cli::{COMMANDS, ParamKind, find_cmd};
+    use colored::*;
+    use rustyline::completion::{Completer, Pair};
+    use rustyline::error::ReadlineError;
+    use rustyline::highlight::Highlighter;
+    use rustyline::hint::Hinter;
+    use rustyline::history::DefaultHistory;
+    use rustyline::validate::Validator;
+    use rustyline::{CompletionType, Config, Context, Editor, Helper};
+    use spacetraders::item_meta::ILM;
+    use std::borrow::Cow;
+
+    const HISTORY_FILE: &str = "history.txt";
+
+    // Completes and hints from the command table
+    #[derive(Default)]
+    struct CmdHelper {
+        // (id, name) of entities in jump range, refreshed before each prompt
+        nearby: Vec<(i32, String)>,
     }
-    impl Position {
-        pub fn new(x: i32, y: i32) -> Self {
-            Position { x, y }
-        }
-        pub fn random(max: i32) -> Self {
-            let mut rng = rand::rng();
-            Position {
-                x: rng.random_range(0..max),
-                y: rng.random_range(0..max),
+
+    impl CmdHelper {
+        // Candidates for the word at `index`, the command itself is word 0
+        fn candidates(&self, words: &[&str], index: usize, prefix: &str) -> Vec<Pair> {
+            let pair = |display: String, replacement: String| Pair {
+                display,
+                replacement,
+            };
+            if index == 0 {
+                let mut names: Vec<&str> = COMMANDS
+                    .iter()
+                    .flat_map(|meta| [meta.full, meta.short])
+                    .filter(|name| name.starts_with(prefix))
+                    .collect();
+                names.sort();
+                return names
+                    .into_iter()
+                    .map(|name| pair(name.to_string(), name.to_string()))
+                    .collect();
+            }
+            let Some(meta) = find_cmd(words[0]) else {
+                return Vec::new();
+            };
+            let Some(param) = meta.params.get(index - 1) else {
+                return Vec::new();
+            };
+            match param.kind {
+                ParamKind::EntId => self
+                    .nearby
+                    .iter()
+                    .filter(|(id, _)| id.to_string().starts_with(prefix))
+                    .map(|(id, name)| pair(format!("{} {}", id, name), id.to_string()))
+                    .collect(),
+                ParamKind::Item => ILM
+                    .ids()
+                    .filter_map(|item| ILM.get(&item))
+                    .filter(|meta| meta.sname.starts_with(prefix))
+                    .map(|meta| pair(meta.sname.clone(), meta.sname.clone()))
+                    .collect(),
+                ParamKind::Choice { options } => options
+                    .iter()
+                    .filter(|option| option.starts_with(prefix))
+                    .map(|option| pair(option.to_string(), option.to_string()))
+                    .collect(),
+                _ => Vec::new(),
             }
         }
-        pub fn distance(&self, other: &Position) -> i32 {
-            let dx = (other.x - self.x) as f64;
-            let dy = (other.y - self.y) as f64;
-            (dx * dx + dy * dy).sqrt() as i32
-        }
     }
-}
-
-mod item_name {
-    use serde::{Deserialize, Serialize};
-
-    #[derive(Hash, Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
-    pub enum ItemName {
-        MetalLow,
-        MetalMid,
-        MetalHigh,
-        CompositeLow,
-        CompositeMid,
-        CompositeHigh,
-        PolymerLow,
-        PolymerMid,
-        PolymerHigh,
-    }
-
-    pub static ITEM_NAMES: [ItemName; 9] = [
-        ItemName::MetalLow,
-        ItemName::MetalMid,
-        ItemName::MetalHigh,
-        ItemName::CompositeLow,
-        ItemName::CompositeMid,
-        ItemName::CompositeHigh,
-        ItemName::PolymerLow,
-        ItemName::PolymerMid,
-        ItemName::PolymerHigh,
-    ];
-}
 
-mod item_meta {
-    use crate::item_name::ItemName;
+    impl Completer for CmdHelper {
+        type Candidate = Pair;
 
-    #[derive(Debug)]
-    pub struct ItemMeta {
-        pub fname: String,
-        pub sname: String, // Should be 4 chars or less
-        pub vol_pc: i32,
-        pub base_val: i32,
-        pub rarity: i32, // 1-100
-    }
-
-    use std::collections::HashMap;
-    pub struct InvListMeta(HashMap<ItemName, ItemMeta>);
-    impl InvListMeta {
-        pub fn new() -> Self {
-            let mut map = HashMap::new();
-            map.insert(
-                ItemName::MetalLow,
-                ItemMeta {
-                    fname: "Metals (low grade)".to_string(),
-                    sname: "mtll".to_string(),
-                    vol_pc: 10,
-                    base_val: 10,
-                    rarity: 1,
-                },
-            );
-            map.insert(
-                ItemName::MetalMid,
-                ItemMeta {
-                    fname: "Metals (mid grade)".to_string(),
-                    sname: "mtlm".to_string(),
-                    vol_pc: 20,
-                    base_val: 20,
-                    rarity: 2,
-                },
-            );
-            map.insert(
-                ItemName::MetalHigh,
-                ItemMeta {
-                    fname: "Metals (high grade)".to_string(),
-                    sname: "mtlh".to_string(),
-                    vol_pc: 30,
-                    base_val: 30,
-                    rarity: 3,
-                },
-            );
-            map.insert(
-                ItemName::CompositeLow,
-                ItemMeta {
-                    fname: "Composites (low grade)".to_string(),
-                    sname: "cmpl".to_string(),
-                    vol_pc: 15,
-                    base_val: 25,
-                    rarity: 10,
-                },
-            );
-            map.insert(
-                ItemName::CompositeMid,
-                ItemMeta {
-                    fname: "Composites (mid grade)".to_string(),
-                    sname: "cmpm".to_string(),
-                    vol_pc: 25,
-                    base_val: 35,
-                    rarity: 20,
-                },
-            );
-            map.insert(
-                ItemName::CompositeHigh,
-                ItemMeta {
-                    fname: "Composites (high grade)".to_string(),
-                    sname: "cmph".to_string(),
-                    vol_pc: 35,
-                    base_val: 45,
-                    rarity: 30,
-                },
-            );
-            map.insert(
-                ItemName::PolymerLow,
-                ItemMeta {
-                    fname: "Polymers (low grade)".to_string(),
-                    sname: "plyl".to_string(),
-                    vol_pc: 12,
-                    base_val: 20,
-                    rarity: 10,
-                },
-            );
-            map.insert(
-                ItemName::PolymerMid,
-                ItemMeta {
-                    fname: "Polymers (mid grade)".to_string(),
-                    sname: "plym".to_string(),
-                    vol_pc: 22,
-                    base_val: 30,
-                    rarity: 20,
-                },
-            );
-            map.insert(
-                ItemName::PolymerHigh,
-                ItemMeta {
-                    fname: "Polymers (high grade)".to_string(),
-                    sname: "plyh".to_string(),
-                    vol_pc: 32,
-                    base_val: 40,
-                    rarity: 30,
-                },
-            );
-            InvListMeta(map)
-        }
-        // Get item meta by enum
-        pub fn get_by_enum(&self, item: &ItemName) -> Option<&ItemMeta> {
-            self.0.get(item)
-        }
-        // Get item meta by string representation of enum
-        pub fn get_by_str(&self, item_str: &str) -> Option<&ItemMeta> {
-            for (item_enum, meta) in self.0.iter() {
-                if format!("{:?}", item_enum).to_lowercase() == item_str.to_lowercase() {
-                    return Some(meta);
-                }
-            }
-            None
-        }
-        // Get item meta by short name
-        pub fn get_by_sname(&self, sname: &str) -> Option<&ItemMeta> {
-            for (_, meta) in self.0.iter() {
-                if meta.sname.to_lowercase() == sname.to_lowercase() {
-                    return Some(meta);
-                }
-            }
-            None
+        fn complete(
+            &self,
+            line: &str,
+            pos: usize,
+            _ctx: &Context<'_>,
+        ) -> rustyline::Result<(usize, Vec<Pair>)> {
+            let before = &line[..pos];
+            let start = before.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+            let words: Vec<&str> = before[..start].split_whitespace().collect();
+            let candidates = self.candidates(&words, words.len(), &before[start..]);
+            Ok((start, candidates))
         }
     }
 
-    use std::sync::LazyLock;
-    pub static ILM: LazyLock<InvListMeta> = LazyLock::new(|| InvListMeta::new());
-}
-
-mod inv_store {
-    use crate::item_name::{ITEM_NAMES, ItemName};
-    use serde::{Deserialize, Serialize};
-    use std::collections::HashMap;
+    impl Hinter for CmdHelper {
+        type Hint = String;
 
-    #[derive(Debug, Clone, Serialize, Deserialize)]
-    // Can be used for stock, price lists, etc.
-    pub struct InvStore(HashMap<ItemName, i32>);
-    impl InvStore {
-        pub fn new() -> Self {
-            let mut store: InvStore = InvStore(HashMap::new());
-            for item in ITEM_NAMES.iter() {
-                store.0.insert(item.clone(), 0);
+        // The params still to type, e.g. "buy 12 " hints "<item> <qty>"
+        fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<String> {
+            if pos < line.len() {
+                return None;
             }
-            store
-        }
-        pub fn set(&mut self, item: ItemName, quantity: i32) {
-            self.0.insert(item, quantity);
-        }
-        pub fn get(&self, item: &ItemName) -> Option<&i32> {
-            self.0.get(item)
-        }
-        pub fn modify(&mut self, item: ItemName, delta: i32) {
-            let entry = self.0.entry(item).or_insert(0);
-            *entry += delta;
-            if *entry < 0 {
-                *entry = 0;
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let meta = find_cmd(words.first()?)?;
+            let rest = meta.params.get(words.len() - 1..)?;
+            if rest.is_empty() {
+                return None;
             }
-        }
-        pub fn items(&self) -> Vec<(&ItemName, &i32)> {
-            self.0.iter().collect()
+            let params: Vec<String> = rest.iter().map(|param| param.usage()).collect();
+            let gap = if line.ends_with(char::is_whitespace) {
+                ""
+            } else {
+                " "
+            };
+            Some(format!("{}{}", gap, params.join(" ")))
         }
     }
-}
 
-mod cargo_hold {
-    use crate::inv_store::InvStore;
-    use crate::item_meta::ILM;
-    use crate::item_name::ItemName;
-    use serde::{Deserialize, Serialize};
-
-    #[derive(Debug, Clone, Serialize, Deserialize)]
-    pub struct CargoHold {
-        pub vol_max: i32,
-        pub vol: i32,
-        pub inv: InvStore,
-    }
-    impl CargoHold {
-        pub fn new(vol_max: i32) -> Self {
-            CargoHold {
-                vol_max,
-                vol: 0,
-                inv: InvStore::new(),
-            }
-        }
-        pub fn insert(&mut self, item: ItemName, quantity: i32) {
-            self.inv.modify(item, quantity);
-            self.calc_vol();
-        }
-        pub fn remove(&mut self, item: ItemName, quantity: i32) {
-            self.inv.modify(item, -quantity);
-            self.calc_vol();
-        }
-        fn calc_vol(&mut self) {
-            let mut total_vol = 0;
-            for (item, qty) in self.inv.items() {
-                if let Some(meta) = ILM.get_by_enum(item) {
-                    total_vol += meta.vol_pc * (*qty);
-                }
-            }
-            self.vol = total_vol;
+    impl Highlighter for CmdHelper {
+        fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+            Cow::Owned(hint.dimmed().to_string())
         }
     }
-}
 
-mod jump_drive {
-    use crate::pos;
-    use serde::{Deserialize, Serialize};
+    impl Validator for CmdHelper {}
+
+    impl Helper for CmdHelper {}
 
-    #[derive(Debug, Clone, Serialize, Deserialize)]
-    pub struct JumpRes {
-        pub success: bool,
-        pub message: String,
-        pub distance: i32,
-        pub fuel_used: i32,
+    // One editor for the whole session, history is loaded once
+    pub struct Prompt {
+        rl: Editor<CmdHelper, DefaultHistory>,
     }
-    impl JumpRes {
+
+    impl Prompt {
         pub fn new() -> Self {
-            JumpRes {
-                success: false,
-                message: String::new(),
-                distance: 0,
-                fuel_used: 0,
-            }
-        }
-    }
-
-    #[derive(Debug, Clone, Serialize, Deserialize)]
-    pub struct JumpDrive {
-        pub fuel_per_ly: i32,
-        pub max_range: i32,
-        pub fuel_max: i32,
-        pub fuel_cur: i32,
-    }
-    impl JumpDrive {
-        pub fn new(fuel_per_ly: i32, max_range: i32) -> Self {
-            let fuel = fuel_per_ly * max_range * 8;
-            JumpDrive {
-                fuel_per_ly,
-                max_range,
-                fuel_max: fuel,
-                fuel_cur: fuel,
-            }
-        }
-        pub fn jump(&mut self, from: &pos::Position, to: &pos::Position) -> JumpRes {
-            let mut res = JumpRes::new();
-            let distance = from.distance(to);
-            if distance > self.max_range {
-                res.message = format!(
-                    "Jump failed: distance {} exceeds max range {}",
-                    distance, self.max_range
-                );
-                return res;
-            }
-            let fuel_needed = self.calc_fuel(distance);
-            if fuel_needed > self.fuel_cur {
-                res.message = format!(
-                    "Jump failed: not enough fuel (need {}, have {})",
-                    fuel_needed, self.fuel_cur
-                );
-                return res;
-            }
-            self.consume(fuel_needed);
-            res.message = format!(
-                "Jump successful: traveled {} light years, consumed {} fuel",
-                distance, fuel_needed
-            );
-            res.success = true;
-            res.distance = distance;
-            res.fuel_used = fuel_needed;
-            res
-        }
-        pub fn refuel_amt(&self) -> i32 {
-            self.fuel_max - self.fuel_cur
+            // List every match on tab, like a shell
+            let config = Config::builder()
+                .completion_type(CompletionType::List)
+                .build();
+            let mut rl = Editor::with_config(config).unwrap();
+            rl.set_helper(Some(CmdHelper::default()));
+
+            // Load history from a file (ignore errors if file doesn't exist)
+            let _ = rl.load_history(HISTORY_FILE);
+            Prompt { rl }
         }
-        pub fn refuel(&mut self, amount: i32) {
-            self.fuel_cur += amount;
-            if self.fuel_cur > self.fuel_max {
-                self.fuel_cur = self.fuel_max;
+
+        // Entity IDs offered when a command wants one
+        pub fn set_nearby(&mut self, nearby: Vec<(i32, String)>) {
+            if let Some(helper) = self.rl.helper_mut() {
+                helper.nearby = nearby;
             }
         }
-        pub fn print(&self) {
-            println!("Jump Drive: {}/{}g", self.fuel_cur, self.fuel_max);
-            println!("Max Range: {} ly", self.max_range);
-            println!("Fuel per ly: {}", self.fuel_per_ly);
-        }
-        pub fn calc_fuel(&self, distance: i32) -> i32 {
-            distance * self.fuel_per_ly
-        }
-        pub fn fuel_str(&self) -> String {
-            format!("{}/{} g", self.fuel_cur, self.fuel_max)
-        }
-        fn consume(&mut self, amount: i32) {
-            self.fuel_cur -= amount;
-            if self.fuel_cur < 0 {
-                self.fuel_cur = 0;
+
+        // None once input has ended (Ctrl-D or end of piped input)
+        // No prompt text when `show` is off, keeps --json output clean
+        pub fn read(&mut self, show: bool) -> Option<String> {
+            let prompt_str = if show {
+                format!("{} | ", ch::SP1).bright_green().to_string()
+            } else {
+                String::new()
+            };
+
+            match self.rl.readline(&prompt_str) {
+                Ok(line) => {
+                    let _ = self.rl.add_history_entry(line.as_str());
+                    let _ = self.rl.save_history(HISTORY_FILE);
+                    Some(line.trim().to_string())
+                }
+                Err(ReadlineError::Eof) => None,
+                Err(_) => Some(String::new()),
             }
         }
     }
 }
 
-mod entity {
-    use crate::cargo_hold::CargoHold;
-    use crate::inv_store::InvStore;
-    use crate::jump_drive::JumpDrive;
-    use crate::jump_drive::JumpRes;
-    use crate::pos;
-    use serde::{Deserialize, Serialize};
+// CLI functions call actions::
+// Handle IO
+mod cli {
+    use crate::{ch, fmt};
+    use colored::*;
+    use serde::Serialize;
+    use serde_json::json;
+    use spacetraders::entity::{Entity, EntityClass};
+    use spacetraders::entity_list::EntityList;
+    use spacetraders::game_state::SessionState;
+    use spacetraders::gm::{GM, GMError, JumpCheck};
+    use spacetraders::item_id::ItemId;
+    use spacetraders::item_meta::ILM;
+    use spacetraders::market;
+    use spacetraders::pos::Position;
+    use spacetraders::route::RouteMode;
+    use spacetraders::slots::{DEFAULT_SLOT, MAX_AUTOSAVE_KEEP, SaveSlots};
+    use std::cell::Cell;
+
+    // What an argument has to look like, checked before the handler runs
+    #[derive(Serialize, Debug, Clone, Copy)]
+    #[serde(tag = "kind")]
+    pub enum ParamKind {
+        // "@" and "#" are swapped for IDs before parsing
+        EntId,
+        Int { min: i32, max: i32 },
+        // One axis of a position, within the galaxy
+        Coord,
+        // One axis of a move, at most a galaxy across
+        Offset,
+        // Short name from the item catalogue
+        Item,
+        Choice { options: &'static [&'static str] },
+        // A single word, slot names and file paths
+        Word,
+        // Everything left on the line
+        Rest,
+    }
+    const ANY_INT: ParamKind = ParamKind::Int {
+        min: i32::MIN,
+        max: i32::MAX,
+    };
+    const ROUTE_MODE: ParamKind = ParamKind::Choice {
+        options: &["fuel", "hops"],
+    };
+    const SCRIPT_OPT: ParamKind = ParamKind::Choice {
+        options: &["stop", "echo"],
+    };
 
-    #[derive(Debug, Clone, Serialize, Deserialize)]
-    pub struct EntityFlags {
-        pub has_dock: bool,
+    #[derive(Serialize)]
+    pub struct Param {
+        pub name: &'static str,
+        #[serde(flatten)]
+        pub kind: ParamKind,
+        // Optional params only come after the required ones
+        pub optional: bool,
     }
-    impl EntityFlags {
-        pub fn new() -> Self {
-            EntityFlags { has_dock: false }
+    const fn req(name: &'static str, kind: ParamKind) -> Param {
+        Param {
+            name,
+            kind,
+            optional: false,
         }
     }
-
-    #[derive(Debug, Clone, Serialize, Deserialize)]
-    pub struct EntityFinance {
-        pub credits: i32,
-        pub prices: InvStore,
-        pub profit_margin: i32, // Percentage
+    const fn opt(name: &'static str, kind: ParamKind) -> Param {
+        Param {
+            name,
+            kind,
+            optional: true,
+        }
     }
-    impl EntityFinance {
-        pub fn new() -> Self {
-            EntityFinance {
-                credits: 10000,
-                prices: InvStore::new(),
-                profit_margin: 20,
-            }
-        }
-    }
-
-    #[derive(Hash, Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
-    pub enum EntityClass {
-        Station,
-        Craft,
-    }
-
-    pub static ENTITY_CLASSES: [EntityClass; 2] = [EntityClass::Station, EntityClass::Craft];
-
-    #[derive(Debug, Clone, Serialize, Deserialize)]
-    pub struct Entity {
-        pub name: String,
-        pub id: i32,
-        pub class: EntityClass,
-        pub pos: pos::Position,
-        pub fin: EntityFinance,
-        pub hold: CargoHold,
-        pub jump_drive: JumpDrive,
-        pub flags: EntityFlags,
-        pub docked_id: Option<i32>,
-        pub targeting_id: Option<i32>,
-    }
-    impl Entity {
-        pub fn new(name: &str) -> Self {
-            Entity {
-                name: name.to_string(),
-                id: 0, // Set my list.add
-                class: EntityClass::Craft,
-                pos: pos::Position::new(0, 0),
-                fin: EntityFinance::new(),
-                hold: CargoHold::new(1000),
-                jump_drive: JumpDrive::new(10, 100),
-                flags: EntityFlags::new(),
-                docked_id: None,
-                targeting_id: None,
-            }
-        }
-        pub fn set_pos(&mut self, position: pos::Position) {
-            self.pos = position;
-        }
-        pub fn jump(&mut self, destination: &pos::Position) -> JumpRes {
-            // Check if docked
-            let mut res = JumpRes::new();
-            if self.docked_id.is_some() {
-                res.success = false;
-                res.message = "Cannot jump while docked.".to_string();
-                return res;
-            }
-            res = self.jump_drive.jump(&self.pos, destination);
-            if res.success {
-                self.pos = destination.clone();
-                res.success = true;
-                res
+    impl Param {
+        // <name> or [name] for usage lines
+        pub fn usage(&self) -> String {
+            let label = match self.kind {
+                ParamKind::Choice { options } => options.join("|"),
+                ParamKind::Rest => format!("{}...", self.name),
+                _ => self.name.to_string(),
+            };
+            if self.optional {
+                format!("[{}]", label)
             } else {
-                res.success = false;
-                res
+                format!("<{}>", label)
             }
         }
-    }
-}
 
-mod entity_maker {
-    use crate::entity::Entity;
-    use crate::entity::EntityClass;
-    use crate::inv_store::InvStore;
-    use crate::item_meta::ILM;
-    use crate::pos::Position;
-    use crate::univ::UNIV;
-    use rand::Rng;
-    pub fn station(name_list: &Vec<String>) -> Entity {
-        fn random_name(station_names: &Vec<String>) -> String {
-            let mut rng = rand::rng();
-            let index1 = rng.random_range(0..station_names.len());
-            let index2 = rng.random_range(0..station_names.len());
-            let index3 = rng.random_range(0..station_names.len());
-            format!(
-                "{}-{}-{}",
-                station_names[index1], station_names[index2], station_names[index3]
-            )
-        }
-        fn random_prices() -> InvStore {
-            let mut rng = rand::rng();
-            // We need a mutable and immutable InvStore
-            let list = InvStore::new();
-            let mut prices = InvStore::new();
-            for (item, qty) in list.items() {
-                if let Some(meta) = ILM.get_by_enum(item) {
-                    let price = meta.base_val + rng.random_range(-5..15);
-                    prices.set(item.clone(), price);
+        // `gal_size` bounds Coord and Offset params
+        fn parse(&self, word: &str, gal_size: i32) -> Result<Arg, ArgError> {
+            let invalid = || ArgError::Invalid {
+                param: self.name,
+                kind: self.kind,
+                value: word.to_string(),
+            };
+            let bounded = |limit: i32| match word.parse::<i32>() {
+                Ok(num) if num.unsigned_abs() <= limit.unsigned_abs() => Ok(Arg::Int(num)),
+                Ok(_) => Err(ArgError::OutOfBounds {
+                    param: self.name,
+                    value: word.to_string(),
+                    limit,
+                }),
+                Err(_) => Err(invalid()),
+            };
+            match self.kind {
+                ParamKind::EntId => word.parse().map(Arg::Int).map_err(|_| invalid()),
+                ParamKind::Coord => bounded(gal_size),
+                ParamKind::Offset => bounded(gal_size.saturating_mul(2)),
+                ParamKind::Int { min, max } => match word.parse() {
+                    Ok(num) if (min..=max).contains(&num) => Ok(Arg::Int(num)),
+                    _ => Err(invalid()),
+                },
+                ParamKind::Item => ILM.id_by_sname(word).map(Arg::Item).ok_or_else(invalid),
+                ParamKind::Choice { options } if !options.contains(&word) => Err(invalid()),
+                ParamKind::Choice { .. } | ParamKind::Word | ParamKind::Rest => {
+                    Ok(Arg::Word(word.to_string()))
                 }
             }
-            prices
         }
-        let mut ent = Entity::new(random_name(name_list).as_str());
-        ent.fin.prices = random_prices();
-        ent.class = EntityClass::Station;
-        ent.flags.has_dock = true;
-        ent.set_pos(Position::random(UNIV.gal_size));
-        ent
     }
-}
 
-mod entity_list {
-    use crate::entity::Entity;
-    use crate::entity_maker;
-    use crate::pos::Position;
-    use serde::{Deserialize, Serialize};
+    enum Arg {
+        Int(i32),
+        Item(ItemId),
+        Word(String),
+    }
 
-    #[derive(Debug, Serialize, Deserialize)]
-    pub struct EntityList {
-        id_acc: i32,
-        entities: Vec<Entity>,
+    // Parsed arguments by param name
+    // Required ones are always there, so handlers can unwrap them
+    #[derive(Default)]
+    pub struct Args {
+        vals: Vec<(&'static str, Arg)>,
     }
-    impl EntityList {
-        pub fn new() -> Self {
-            EntityList {
-                entities: Vec::new(),
-                id_acc: 0,
-            }
-        }
-        pub fn add(&mut self, mut entity: Entity) {
-            entity.id = self.id_acc;
-            self.entities.push(entity);
-            self.id_acc += 1;
-        }
-        pub fn get(&self, index: i32) -> Option<&Entity> {
-            self.entities.get(index as usize)
-        }
-        pub fn get_mut(&mut self, index: i32) -> Option<&mut Entity> {
-            self.entities.get_mut(index as usize)
-        }
-        pub fn get_by_id(&self, id: i32) -> Option<&Entity> {
-            self.entities.iter().find(|ent| ent.id == id)
-        }
-        pub fn get_by_id_mut(&mut self, id: i32) -> Option<&mut Entity> {
-            self.entities.iter_mut().find(|ent| ent.id == id)
+    impl Args {
+        fn get(&self, name: &str) -> Option<&Arg> {
+            self.vals
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, arg)| arg)
         }
-        pub fn generate_entities(&mut self, name_list: &Vec<String>, count: usize) {
-            for i in 0..count {
-                let ent = entity_maker::station(name_list);
-                self.add(ent);
+        pub fn int(&self, name: &str) -> Option<i32> {
+            match self.get(name) {
+                Some(Arg::Int(num)) => Some(*num),
+                _ => None,
             }
         }
-        pub fn get_player(&self) -> Option<&Entity> {
-            self.get(0)
-        }
-        pub fn get_player_mut(&mut self) -> Option<&mut Entity> {
-            self.get_mut(0)
-        }
-        pub fn set_player(&mut self, ship: Entity) {
-            if let Some(player) = self.get_mut(0) {
-                *player = ship;
+        pub fn item(&self, name: &str) -> Option<ItemId> {
+            match self.get(name) {
+                Some(Arg::Item(item)) => Some(*item),
+                _ => None,
             }
         }
-        pub fn list(&self) -> Vec<&Entity> {
-            self.entities.iter().collect()
+        pub fn word(&self, name: &str) -> Option<&str> {
+            self.words(name).next()
         }
-        pub fn list_by_distance(&self, origin: Position, max_distance: i32) -> Vec<&Entity> {
-            let list = self
-                .entities
-                .iter()
-                .filter(|ent| origin.distance(&ent.pos) <= max_distance)
-                .collect();
-            // Sort by distance
-            let mut sorted_list: Vec<&Entity> = list;
-            sorted_list.sort_by_key(|ent| origin.distance(&ent.pos));
-            sorted_list
-        }
-        pub fn print(&self, ship: &Entity) {
-            for (i, ent) in self.entities.iter().enumerate() {
-                let distance = ship.pos.distance(&ent.pos);
-                println!(
-                    "{}: {} [{}] ({} {})",
-                    ent.id, ent.name, distance, ent.pos.x, ent.pos.y
-                );
-            }
+        // Every value of a param that can be given more than once
+        pub fn words(&self, name: &str) -> impl Iterator<Item = &str> {
+            self.vals.iter().filter_map(move |(n, arg)| match arg {
+                Arg::Word(word) if *n == name => Some(word.as_str()),
+                _ => None,
+            })
         }
     }
-}
-
-mod input {
-    use crate::ch;
-    use colored::*;
-    use rustyline::Editor;
-
-    pub fn prompt() -> String {
-        // Create an Editor instance
-        let mut rl = Editor::<(), rustyline::history::DefaultHistory>::new().unwrap();
-
-        // Load history from a file (ignore errors if file doesn't exist)
-        let _ = rl.load_history("history.txt");
-
-        let prompt_str = format!("{} | ", ch::SP1).bright_green().to_string();
 
-        match rl.readline(&prompt_str) {
-            Ok(line) => {
-                let _ = rl.add_history_entry(line.as_str());
-                let _ = rl.save_history("history.txt");
-                line.trim().to_string()
+    #[derive(Debug)]
+    pub enum ArgError {
+        Missing {
+            param: &'static str,
+        },
+        Invalid {
+            param: &'static str,
+            kind: ParamKind,
+            value: String,
+        },
+        TooMany {
+            extra: String,
+        },
+        // A Coord or Offset past `limit` either way
+        OutOfBounds {
+            param: &'static str,
+            value: String,
+            limit: i32,
+        },
+    }
+    impl std::fmt::Display for ArgError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                ArgError::Missing { param } => write!(f, "Missing <{}>", param),
+                ArgError::Invalid { param, kind, value } => match kind {
+                    ParamKind::EntId => write!(f, "Invalid entity ID: '{}'", value),
+                    ParamKind::Coord | ParamKind::Offset => {
+                        write!(f, "Invalid {} coordinate: '{}'", param, value)
+                    }
+                    ParamKind::Item => write!(f, "Invalid item sname: '{}'", value),
+                    ParamKind::Int { min: i32::MIN, .. } => {
+                        write!(f, "Invalid {} '{}', use a number", param, value)
+                    }
+                    ParamKind::Int { min, max: i32::MAX } => write!(
+                        f,
+                        "Invalid {} '{}', use a number of at least {}",
+                        param, value, min
+                    ),
+                    ParamKind::Int { min, max } => write!(
+                        f,
+                        "Invalid {} '{}', use a number from {} to {}",
+                        param, value, min, max
+                    ),
+                    ParamKind::Choice { options } => {
+                        write!(
+                            f,
+                            "Invalid {} '{}', use {}",
+                            param,
+                            value,
+                            options.join(" or ")
+                        )
+                    }
+                    ParamKind::Word | ParamKind::Rest => {
+                        write!(f, "Invalid {}: '{}'", param, value)
+                    }
+                },
+                ArgError::TooMany { extra } => write!(f, "Unexpected '{}'", extra),
+                ArgError::OutOfBounds {
+                    param,
+                    value,
+                    limit,
+                } => write!(
+                    f,
+                    "Invalid {} '{}', use a number from -{} to {}",
+                    param, value, limit, limit
+                ),
             }
-            Err(_) => String::new(),
         }
     }
-}
-
-// Actions represent things the player can do
-// They should never print anything
-// Formatting and UX is up to ui functions
-// Galaxy Manager
-mod gm {
-
-    use crate::entity::{Entity, EntityClass};
-    use crate::entity_list::EntityList;
-    use crate::item_meta::ItemMeta;
-    use crate::jump_drive::JumpRes;
-    use crate::pos::Position;
-
-    #[derive(Debug, PartialEq, Clone)]
-    pub enum GMRS {
-        Success,
-        Failure, // Generic failure
-        TooFar,
-        NotDocked,
-        NotEnoughFuel,
-    }
-
-    pub struct GMRes {
-        pub status: GMRS,
-    }
-
-    pub struct GMResMsg {
-        pub status: GMRS,
-        pub message: String,
-    }
-
-    pub struct GMResJumpCheck {
-        pub status: GMRS,
-        pub distance: i32,
-        pub fuel_needed: i32,
-        pub fuel_cur: i32,
-        pub fuel_after: i32,
-        pub can_jump: bool,
-    }
-
-    // FIXME: THIS NEEDS TO USE GMRS
-    // GMResJump is an alias for JumpRes
-    pub type GMResJump = JumpRes;
-    pub struct GMResEntList {
-        pub entities: Vec<Entity>,
-    }
+    impl std::error::Error for ArgError {}
 
-    pub struct GMResBuy {
-        pub status: GMRS,
-        pub message: String,
-    }
+    // Runs once the arguments have parsed
+    type Handler = fn(&mut CLI, &Args, &mut EntityList) -> Flow;
 
-    pub struct GM {
-        pub tick: i32,
+    #[derive(Serialize)]
+    pub struct CmdMeta {
+        pub full: &'static str,
+        pub short: &'static str,
+        pub params: &'static [Param],
+        pub desc: &'static str,
+        #[serde(skip)]
+        run: Handler,
     }
-    impl GM {
-        pub fn new() -> Self {
-            GM { tick: 0 }
-        }
-        pub fn set_target(&self, player: &mut Entity, ent_id: i32) -> GMRes {
-            player.targeting_id = Some(ent_id);
-            GMRes {
-                status: GMRS::Success,
-            }
-        }
-
-        pub fn jump_check(&self, player: &Entity, target: &Position) -> GMResJumpCheck {
-            let distance = player.pos.distance(target);
-            let fuel_needed = player.jump_drive.calc_fuel(distance);
-            let fuel_cur = player.jump_drive.fuel_cur;
-            let fuel_after = fuel_cur - fuel_needed;
-            let can_jump = distance <= player.jump_drive.max_range && fuel_needed <= fuel_cur;
-            GMResJumpCheck {
-                status: GMRS::Success,
-                distance,
-                fuel_needed,
-                fuel_cur,
-                fuel_after,
-                can_jump,
-            }
-        }
-
-        // FIXME: THIS NEEDS TO USE GMRS
-        pub fn jump(&mut self, player: &mut Entity, destination: &Position) -> GMResJump {
-            self.tick += 1;
-            player.jump(&destination)
-        }
-
-        pub fn dock_list(&self, player: &Entity, ent_list: &EntityList) -> GMResEntList {
-            let nearby_stations: Vec<&Entity> = ent_list
-                .list_by_distance(player.pos, 1)
-                .into_iter()
-                .filter(|ent| ent.flags.has_dock)
-                .collect();
-            GMResEntList {
-                entities: nearby_stations.into_iter().cloned().collect(),
-            }
-        }
-
-        pub fn dock(&self, ent_list: &mut EntityList, ent_id: i32) -> GMResMsg {
-            let mut res = GMResMsg {
-                status: GMRS::Failure,
-                message: String::new(),
-            };
-            if let Some(target) = ent_list.get_by_id(ent_id) {
-                if !target.flags.has_dock {
-                    res.message =
-                        format!("Entity ID {} does not have docking capabilities.", ent_id);
-                    return res;
+    impl CmdMeta {
+        // e.g. "buy <ent_id> <item> <qty>"
+        pub fn usage(&self) -> String {
+            let mut parts = vec![self.full.to_string()];
+            parts.extend(self.params.iter().map(Param::usage));
+            parts.join(" ")
+        }
+
+        pub fn parse(&self, words: &[&str], gal_size: i32) -> Result<Args, ArgError> {
+            let mut args = Args::default();
+            let mut i = 0;
+            for param in self.params {
+                if i >= words.len() {
+                    if param.optional {
+                        break;
+                    }
+                    return Err(ArgError::Missing { param: param.name });
                 }
-                // Clone/copy the needed data to end the immutable borrow
-                let target_pos = target.pos.clone();
-                let target_name = target.name.clone();
-
-                let ship = ent_list.get_player_mut().unwrap();
-                if ship.pos.distance(&target_pos) <= 1 {
-                    res.status = GMRS::Success;
-                    res.message = format!("Docked with {}.", target_name);
-                    ship.docked_id = Some(ent_id);
+                let arg = if let ParamKind::Rest = param.kind {
+                    let rest = words[i..].join(" ");
+                    i = words.len();
+                    Arg::Word(rest)
                 } else {
-                    res.message = format!("Docking failed: not close enough to {}.", target_name);
-                }
-            } else {
-                res.message = format!("No entity found with ID {}.", ent_id);
-            }
-            res
-        }
-
-        pub fn undock(&self, player: &mut Entity) -> GMResMsg {
-            let mut res = GMResMsg {
-                status: GMRS::Success,
-                message: String::new(),
-            };
-            if let Some(docked_id) = player.docked_id {
-                res.message = format!("Undocked from entity ID {}.", docked_id);
-                player.docked_id = None;
-            } else {
-                res.message = format!("Not currently docked to any entity.");
-            }
-            res
-        }
-
-        pub fn name_ent(&self, ent: &mut Entity, new_name: &str) -> GMResMsg {
-            ent.name = new_name.to_string();
-            GMResMsg {
-                status: GMRS::Success,
-                message: format!("Renamed to {}", ent.name),
-            }
-        }
-
-        pub fn buy(
-            &self,
-            entities: &EntityList,
-            buyer_id: i32,
-            seller_id: i32,
-            item: &ItemMeta,
-            qty: i32,
-        ) -> GMResBuy {
-            let buyer = entities.get_by_id(buyer_id).unwrap();
-            let seller = entities.get_by_id(seller_id).unwrap();
-            // Must be at same position to trade
-            let distance = buyer.pos.distance(&seller.pos);
-            if distance > 0 {
-                return GMResBuy {
-                    status: GMRS::TooFar,
-                    message: format!("Cannot trade: buyer is {} away from seller", distance),
-                };
-            }
-            // Station require docking to trade
-            if seller.class == EntityClass::Station && buyer.docked_id != Some(seller.id) {
-                return GMResBuy {
-                    status: GMRS::NotDocked,
-                    message: format!("Cannot trade: buyer is not docked to seller"),
+                    i += 1;
+                    param.parse(words[i - 1], gal_size)?
                 };
+                args.vals.push((param.name, arg));
             }
-            GMResBuy {
-                status: GMRS::Success,
-                message: "ok".to_string(),
-            }
-        }
-
-        pub fn save(&self, entities: &EntityList, filename: &str) -> GMResMsg {
-            // Serialize entities to JSON and save to file
-            let serialized = serde_json::to_string_pretty(&entities).unwrap();
-            std::fs::write(filename, serialized).expect("Unable to write file");
-            GMResMsg {
-                status: GMRS::Success,
-                message: format!("Saved to {}", filename),
-            }
-        }
-
-        pub fn load(&self, entities: &mut EntityList, filename: &str) -> GMResMsg {
-            // Load entities from JSON file and deserialize
-            let data = std::fs::read_to_string(filename).expect("Unable to read file");
-            *entities = serde_json::from_str(&data).unwrap();
-            GMResMsg {
-                status: GMRS::Success,
-                message: "loaded".to_string(),
+            if i < words.len() {
+                return Err(ArgError::TooMany {
+                    extra: words[i..].join(" "),
+                });
             }
+            Ok(args)
         }
     }
-}
-
-// CLI functions call actions::
-// Handle IO
-mod cli {
-    use crate::entity_list::EntityList;
-    use crate::gm::{GM, GMRS};
-    use crate::item_meta::ILM;
-    use crate::pos::Position;
-    use crate::{
-        ItemName,
-        entity::{Entity, EntityClass},
-    };
-    use crate::{ch, fmt};
-    use colored::*;
-    use std::collections::HashMap;
 
-    #[derive(Hash, Eq, PartialEq, Debug)]
-    pub enum CmdName {
-        Help,
-        Target,
-        Scan,
-        Jump,
-        JumpMan,
-        JumpCheckMan,
-        JumpCheck,
-        JumpRel,
-        JumpCheckRel,
-        Entities,
-        Cargo,
-        Buy,
-        Sell,
-        DockList,
-        Dock,
-        Undock,
-        Refuel,
-        Name,
-        Time,
-        Save,
-        Load,
-        Quit,
+    // Look up a command by full name or short alias
+    pub fn find_cmd(word: &str) -> Option<&'static CmdMeta> {
+        COMMANDS
+            .iter()
+            .find(|meta| word == meta.full || word == meta.short)
     }
-    pub struct CmdMeta {
-        full: &'static str,
-        short: &'static str,
-        params: &'static str, // req opt?
-        desc: &'static str,
-    }
-    type CmdMetaMap = HashMap<CmdName, CmdMeta>;
-    fn make_cli_meta() -> CmdMetaMap {
-        let mut map: CmdMetaMap = HashMap::new();
-        map.insert(
-            CmdName::Help,
-            CmdMeta {
-                full: "help",
-                short: "h",
-                params: "",
-                desc: "Show this help message.",
+
+    pub static COMMANDS: &[CmdMeta] = &[
+        CmdMeta {
+            full: "help",
+            short: "h",
+            params: &[opt("topic", ParamKind::Word)],
+            desc: "Show this help message. Topic is a command, 'full', 'short' or 'min'.",
+            run: |cli, args, _| {
+                cli.help(args);
+                Flow::Continue
             },
-        );
-        map.insert(
-            CmdName::Target,
-            CmdMeta {
-                full: "target",
-                short: "t",
-                params: "ent_id?",
-                desc: "Set or view current target entity by ID.",
+        },
+        CmdMeta {
+            full: "target",
+            short: "t",
+            params: &[opt("ent_id", ParamKind::EntId)],
+            desc: "Set or view current target entity by ID.",
+            run: |cli, args, entities| {
+                cli.target(args, entities);
+                Flow::Continue
             },
-        );
-        map.insert(
-            CmdName::Scan,
-            CmdMeta {
-                full: "scan",
-                short: "s",
-                params: "ent_id?",
-                desc: "Scan an entity by ID or self if no ID provided.",
+        },
+        CmdMeta {
+            full: "scan",
+            short: "s",
+            params: &[opt("ent_id", ParamKind::EntId)],
+            desc: "Scan an entity by ID or self if no ID provided.",
+            run: |cli, args, entities| {
+                cli.scan(args, entities);
+                Flow::Continue
             },
-        );
-        map.insert(
-            CmdName::Jump,
-            CmdMeta {
-                full: "jump",
-                short: "j",
-                params: "ent_id",
-                desc: "Jump to target entity by ID.",
+        },
+        CmdMeta {
+            full: "jump",
+            short: "j",
+            params: &[req("ent_id", ParamKind::EntId)],
+            desc: "Jump to target entity by ID.",
+            run: |cli, args, entities| {
+                cli.jump(args, entities);
+                Flow::Continue
             },
-        );
-        map.insert(
-            CmdName::JumpMan,
-            CmdMeta {
-                full: "jump_man",
-                short: "jm",
-                params: "x y",
-                desc: "Jump to specified coordinates.",
+        },
+        CmdMeta {
+            full: "jump_man",
+            short: "jm",
+            params: &[req("x", ParamKind::Coord), req("y", ParamKind::Coord)],
+            desc: "Jump to specified coordinates.",
+            run: |cli, args, entities| {
+                cli.jump_man(args, entities);
+                Flow::Continue
             },
-        );
-        map.insert(
-            CmdName::JumpCheckMan,
-            CmdMeta {
-                full: "jump_check_man",
-                short: "jcm",
-                params: "x y",
-                desc: "Check jump feasibility to specified coordinates.",
+        },
+        CmdMeta {
+            full: "jump_check_man",
+            short: "jcm",
+            params: &[req("x", ParamKind::Coord), req("y", ParamKind::Coord)],
+            desc: "Check jump feasibility to specified coordinates.",
+            run: |cli, args, entities| {
+                cli.jump_check_man(args, entities);
+                Flow::Continue
             },
-        );
-        map.insert(
-            CmdName::JumpCheck,
-            CmdMeta {
-                full: "jump_check",
-                short: "jc",
-                params: "ent_id",
-                desc: "Check jump feasibility to target entity by ID.",
+        },
+        CmdMeta {
+            full: "jump_check",
+            short: "jc",
+            params: &[req("ent_id", ParamKind::EntId)],
+            desc: "Check jump feasibility to target entity by ID.",
+            run: |cli, args, entities| {
+                cli.jump_check(args, entities);
+                Flow::Continue
             },
-        );
-        map.insert(
-            CmdName::JumpRel,
-            CmdMeta {
-                full: "jump_rel",
-                short: "jr",
-                params: "x y",
-                desc: "Jump to coordinates relative to current position.",
+        },
+        CmdMeta {
+            full: "jump_rel",
+            short: "jr",
+            params: &[req("dx", ParamKind::Offset), req("dy", ParamKind::Offset)],
+            desc: "Jump to coordinates relative to current position.",
+            run: |cli, args, entities| {
+                cli.jump_rel(args, entities);
+                Flow::Continue
             },
-        );
-        map.insert(
-            CmdName::JumpCheckRel,
-            CmdMeta {
-                full: "jump_check_rel",
-                short: "jcr",
-                params: "x y",
-                desc: "Check jump feasibility to coordinates relative to current position.",
+        },
+        CmdMeta {
+            full: "jump_check_rel",
+            short: "jcr",
+            params: &[req("dx", ParamKind::Offset), req("dy", ParamKind::Offset)],
+            desc: "Check jump feasibility to coordinates relative to current position.",
+            run: |cli, args, entities| {
+                cli.jump_check_rel(args, entities);
+                Flow::Continue
             },
-        );
-        map.insert(
-            CmdName::Entities,
-            CmdMeta {
-                full: "entities",
-                short: "l",
-                params: "dist",
-                desc: "List entities within jump range.",
+        },
+        CmdMeta {
+            full: "entities",
+            short: "l",
+            params: &[opt(
+                "dist",
+                ParamKind::Int {
+                    min: 0,
+                    max: i32::MAX,
+                },
+            )],
+            desc: "List entities within jump range, or within dist ly.",
+            run: |cli, args, entities| {
+                cli.entities(args, entities);
+                Flow::Continue
             },
-        );
-        map.insert(
-            CmdName::Cargo,
-            CmdMeta {
-                full: "cargo",
-                short: "c",
-                params: "ent_id?",
-                desc: "View cargo hold contents.",
+        },
+        CmdMeta {
+            full: "cargo",
+            short: "c",
+            params: &[opt("ent_id", ParamKind::EntId)],
+            desc: "View cargo hold contents.",
+            run: |cli, args, entities| {
+                cli.cargo(args, entities);
+                Flow::Continue
             },
-        );
-        map.insert(
-            CmdName::Buy,
-            CmdMeta {
-                full: "buy",
-                short: "b",
-                params: "ent_id item qty",
-                desc: "Buy specified quantity of an item.",
+        },
+        CmdMeta {
+            full: "buy",
+            short: "b",
+            params: &[
+                req("ent_id", ParamKind::EntId),
+                req("item", ParamKind::Item),
+                req("qty", ANY_INT),
+            ],
+            desc: "Buy specified quantity of an item.",
+            run: |cli, args, entities| {
+                cli.buy(args, entities);
+                Flow::Continue
             },
-        );
-        map.insert(
-            CmdName::Sell,
-            CmdMeta {
-                full: "sell",
-                short: "sl",
-                params: "ent_id item qty",
-                desc: "Sell specified quantity of an item.",
+        },
+        CmdMeta {
+            full: "sell",
+            short: "sl",
+            params: &[
+                req("ent_id", ParamKind::EntId),
+                req("item", ParamKind::Item),
+                req("qty", ANY_INT),
+            ],
+            desc: "Sell specified quantity of an item.",
+            run: |cli, args, entities| {
+                cli.sell(args, entities);
+                Flow::Continue
             },
-        );
-        map.insert(
-            CmdName::DockList,
-            CmdMeta {
-                full: "dock_list",
-                short: "dl",
-                params: "",
-                desc: "List nearby docking-capable entities.",
+        },
+        CmdMeta {
+            full: "dock_list",
+            short: "dl",
+            params: &[],
+            desc: "List nearby docking-capable entities.",
+            run: |cli, _, entities| {
+                cli.dock_list(entities);
+                Flow::Continue
             },
-        );
-        map.insert(
-            CmdName::Dock,
-            CmdMeta {
-                full: "dock",
-                short: "d",
-                params: "ent_id",
-                desc: "Dock with a specified entity by ID.",
+        },
+        CmdMeta {
+            full: "dock",
+            short: "d",
+            params: &[req("ent_id", ParamKind::EntId)],
+            desc: "Dock with a specified entity by ID.",
+            run: |cli, args, entities| {
+                cli.dock(args, entities);
+                Flow::Continue
             },
-        );
-        map.insert(
-            CmdName::Undock,
-            CmdMeta {
-                full: "undock",
-                short: "ud",
-                params: "",
-                desc: "Undock from the currently docked entity.",
+        },
+        CmdMeta {
+            full: "undock",
+            short: "ud",
+            params: &[],
+            desc: "Undock from the currently docked entity.",
+            run: |cli, _, entities| {
+                cli.undock(entities);
+                Flow::Continue
             },
-        );
-        map.insert(
-            CmdName::Refuel,
-            CmdMeta {
-                full: "refuel",
-                short: "rf",
-                params: "",
-                desc: "Refuel the ship's jump drive while docked.",
+        },
+        CmdMeta {
+            full: "refuel",
+            short: "rf",
+            params: &[],
+            desc: "Refuel the ship's jump drive while docked.",
+            run: |cli, _, entities| {
+                cli.refuel(entities);
+                Flow::Continue
             },
-        );
-        map.insert(
-            CmdName::Name,
-            CmdMeta {
-                full: "name",
-                short: "n",
-                params: "",
-                desc: "Rename the player's ship.",
+        },
+        CmdMeta {
+            full: "route",
+            short: "r",
+            params: &[req("ent_id", ParamKind::EntId), opt("mode", ROUTE_MODE)],
+            desc: "Plan a multi-jump route to an entity. Mode is 'fuel' (default) or 'hops'.",
+            run: |cli, args, entities| {
+                cli.route(args, entities);
+                Flow::Continue
             },
-        );
-        map.insert(
-            CmdName::Time,
-            CmdMeta {
-                full: "time",
-                short: "ti",
-                params: "",
-                desc: "Show the current game time (tick).",
+        },
+        CmdMeta {
+            full: "travel",
+            short: "tr",
+            params: &[req("ent_id", ParamKind::EntId), opt("mode", ROUTE_MODE)],
+            desc: "Autopilot along a planned route to an entity, refueling on the way.",
+            run: |cli, args, entities| {
+                cli.travel(args, entities);
+                Flow::Continue
             },
-        );
-        map.insert(
-            CmdName::Save,
-            CmdMeta {
-                full: "save",
-                short: "sv",
-                params: "file",
-                desc: "Save the current game state to a file.",
+        },
+        CmdMeta {
+            full: "name",
+            short: "n",
+            params: &[req("new_name", ParamKind::Rest)],
+            desc: "Rename the player's ship.",
+            run: |cli, args, entities| {
+                cli.name(args, entities);
+                Flow::Continue
             },
-        );
-        map.insert(
-            CmdName::Load,
-            CmdMeta {
-                full: "load",
-                short: "ld",
-                params: "file",
-                desc: "Load a game state from a file.",
+        },
+        CmdMeta {
+            full: "time",
+            short: "ti",
+            params: &[],
+            desc: "Show the current game time (tick).",
+            run: |cli, _, _| {
+                cli.time();
+                Flow::Continue
             },
-        );
-        map.insert(
-            CmdName::Quit,
-            CmdMeta {
-                full: "quit",
-                short: "q",
-                params: "",
-                desc: "Exit the game.",
+        },
+        CmdMeta {
+            full: "wait",
+            short: "w",
+            params: &[opt("ticks", ParamKind::Int { min: 1, max: 100 })],
+            desc: "Let time pass so stations produce and restock.",
+            run: |cli, args, entities| {
+                cli.wait(args, entities);
+                Flow::Continue
             },
-        );
-        map
-    }
+        },
+        CmdMeta {
+            full: "save",
+            short: "sv",
+            params: &[opt("slot", ParamKind::Word)],
+            desc: "Save the game to a named slot (or a path ending in .json).",
+            run: |cli, args, entities| {
+                cli.save(args, entities);
+                Flow::Continue
+            },
+        },
+        CmdMeta {
+            full: "load",
+            short: "ld",
+            params: &[opt("slot", ParamKind::Word)],
+            desc: "Load the game from a named slot (or a path ending in .json).",
+            run: |cli, args, entities| {
+                cli.load(args, entities);
+                Flow::Continue
+            },
+        },
+        CmdMeta {
+            full: "saves",
+            short: "svs",
+            params: &[],
+            desc: "List save slots with ship, credits, tick and time saved.",
+            run: |cli, _, _| {
+                cli.saves();
+                Flow::Continue
+            },
+        },
+        CmdMeta {
+            full: "delete",
+            short: "del",
+            params: &[req("slot", ParamKind::Word)],
+            desc: "Delete a save slot.",
+            run: |cli, args, _| {
+                cli.delete(args);
+                Flow::Continue
+            },
+        },
+        CmdMeta {
+            full: "autosave",
+            short: "as",
+            params: &[
+                opt(
+                    "ticks",
+                    ParamKind::Int {
+                        min: 0,
+                        max: i32::MAX,
+                    },
+                ),
+                opt(
+                    "keep",
+                    ParamKind::Int {
+                        min: 1,
+                        max: MAX_AUTOSAVE_KEEP as i32,
+                    },
+                ),
+            ],
+            desc: "Autosave every N ticks into rotating autosave slots. 0 turns it off.",
+            run: |cli, args, _| {
+                cli.autosave(args);
+                Flow::Continue
+            },
+        },
+        CmdMeta {
+            full: "run",
+            short: "rn",
+            params: &[
+                req("file", ParamKind::Word),
+                opt("option", SCRIPT_OPT),
+                opt("option", SCRIPT_OPT),
+            ],
+            desc: "Run commands from a file, one per line.",
+            run: |cli, args, entities| cli.run(args, entities),
+        },
+        CmdMeta {
+            full: "quit",
+            short: "q",
+            params: &[],
+            desc: "Exit the game.",
+            run: |cli, _, _| {
+                cli.quit();
+                Flow::Quit
+            },
+        },
+    ];
     pub struct CLI {
-        pub last_id: i32,
-        pub meta: CmdMetaMap,
+        pub session: SessionState,
+        pub slots: SaveSlots,
         // TODO:
         // Should gm be owned by main and passed to cli?
         // This would make it easier to share with TUI
         pub gm: GM,
+        // Set by err, read by scripts to spot failing commands
+        failed: Cell<bool>,
+        script_depth: usize,
+        // --json, one JSON object per result instead of text
+        pub json: bool,
+        // Full name of the running command, tags JSON output
+        command: Cell<&'static str>,
+    }
+
+    // A single line of --json output
+    #[derive(Serialize)]
+    struct JsonOut<'a, T: Serialize> {
+        command: &'a str,
+        status: &'a str,
+        message: &'a str,
+        data: T,
+    }
+
+    // --json status for a command that worked
+    // Anything else is Failure for bad input or the GMError kind
+    const SUCCESS: &str = "Success";
+    const FAILURE: &str = "Failure";
+
+    // What the input loop should do after a command
+    pub enum Flow {
+        Continue,
+        Quit,
+    }
+
+    #[derive(Default)]
+    pub struct ScriptOpts {
+        pub stop_on_fail: bool,
+        pub echo: bool,
     }
+
+    // Scripts can run scripts, this stops one running itself forever
+    const MAX_SCRIPT_DEPTH: usize = 8;
     impl CLI {
         // Util
 
-        pub fn new() -> Self {
+        pub fn new(gm: GM) -> Self {
             CLI {
-                last_id: 0,
-                meta: make_cli_meta(),
-                gm: GM::new(),
+                session: SessionState::default(),
+                slots: SaveSlots::new(SaveSlots::default_dir()),
+                gm,
+                failed: Cell::new(false),
+                script_depth: 0,
+                json: false,
+                command: Cell::new(""),
             }
         }
 
-        pub fn check_cmd(&self, v: &str, target: CmdName) -> bool {
-            let cmd = self.meta.get(&target).unwrap();
-            v == cmd.full || v == cmd.short
-        }
-
         // Generate a single line entity string
         fn ent_line_str(&self, ent_id: i32, ent_list: &EntityList) -> String {
             let ent = ent_list.get_by_id(ent_id).unwrap();
@@ -1139,6 +904,9 @@ mod cli {
         }
         // Print single line entity string
         fn print_ent_line(&self, ent_id: i32, ent_list: &EntityList) {
+            if self.json || ent_list.get_by_id(ent_id).is_none() {
+                return;
+            }
             let ent_str = self.ent_line_str(ent_id, ent_list);
             println!("{}{:^62}{}", ch::ARL, ent_str, ch::ARR);
         }
@@ -1147,7 +915,7 @@ mod cli {
             if ent_id < 0 {
                 return;
             }
-            self.last_id = ent_id;
+            self.session.last_id = ent_id;
         }
 
         // TODO:
@@ -1157,46 +925,125 @@ mod cli {
 
         // Print an error message
         fn err(&self, msg: &str) {
+            if self.json {
+                self.emit(FAILURE, msg, ());
+                return;
+            }
+            self.failed.set(true);
             println!("{} {}", ch::ERR, msg.red());
         }
 
         // Print a success message
         fn suc(&self, msg: &str) {
+            if self.json {
+                return;
+            }
             println!("{} {}", ch::SUC, msg.green());
         }
 
-        // Commands
+        // Print a refused GM action, `context` leads the text message
+        fn gm_err(&self, context: &str, e: &GMError) {
+            if self.json {
+                self.emit(e.kind(), &e.to_string(), e);
+            } else if context.is_empty() {
+                self.err(&e.to_string());
+            } else {
+                self.err(format!("{}: {}", context, e).as_str());
+            }
+        }
 
-        pub fn intro(&self) {
-            CLI::cli_header("SpaceTrade.rs CLI");
-            println!("Type 'help' for a list of commands.");
+        // Print arguments that didn't fit the command's params
+        fn usage_err(&self, meta: &CmdMeta, e: &ArgError) {
+            if self.json {
+                let msg = format!("{}.", e);
+                self.emit(FAILURE, &msg, json!({ "usage": meta.usage() }));
+            } else {
+                self.err(format!("{}. Usage: {}", e, meta.usage()).as_str());
+            }
         }
 
-        pub fn help(&self, cmd: Vec<&str>) {
-            fn print_full(cmd: &CmdMeta) {
-                print!("{} {}", cmd.full.green(), cmd.params.yellow());
+        // x and y, or dx and dy added to `origin`
+        fn destination(args: &Args, origin: Option<Position>) -> Position {
+            match origin {
+                None => Position::new(args.int("x").unwrap(), args.int("y").unwrap()),
+                Some(from) => Position::new(
+                    from.x.saturating_add(args.int("dx").unwrap()),
+                    from.y.saturating_add(args.int("dy").unwrap()),
+                ),
+            }
+        }
+
+        // The optional ent_id argument, or the player without one
+        fn ent_or_player<'a>(&self, args: &Args, entities: &'a EntityList) -> Option<&'a Entity> {
+            let Some(ent_id) = args.int("ent_id") else {
+                return entities.get_player();
+            };
+            let ent = entities.get_by_id(ent_id);
+            if ent.is_none() {
+                self.gm_err("", &GMError::UnknownEntity { ent_id });
+            }
+            ent
+        }
+
+        // Print one result as a JSON line, anything but Success counts as failed
+        fn emit<T: Serialize>(&self, status: &str, message: &str, data: T) {
+            if status != SUCCESS {
+                self.failed.set(true);
+            }
+            let out = JsonOut {
+                command: self.command.get(),
+                status,
+                message,
+                data,
+            };
+            // Results are plain data, this can't fail
+            if let Ok(line) = serde_json::to_string(&out) {
+                println!("{}", line);
+            }
+        }
+
+        // Commands
+
+        pub fn intro(&self) {
+            if self.json {
+                return;
+            }
+            self.cli_header("SpaceTrade.rs CLI");
+            println!("Type 'help' for a list of commands.");
+        }
+
+        pub fn help(&self, args: &Args) {
+            fn print_full(cmd: &CmdMeta) {
+                let params: Vec<String> = cmd.params.iter().map(Param::usage).collect();
+                print!("{} {}", cmd.full.green(), params.join(" ").yellow());
                 println!(" | {}", cmd.short.green());
                 println!("-- {}", cmd.desc);
             }
-            CLI::cli_header("Help");
-            let mut mode = "short";
-            if cmd.len() >= 2 {
-                mode = cmd[1];
+            self.cli_header("Help");
+            let mode = args.word("topic").unwrap_or("short");
+            let mut cmds: Vec<&CmdMeta> = COMMANDS.iter().collect();
+            cmds.sort_by_key(|cmd| cmd.full);
+            if self.json {
+                if !matches!(mode, "full" | "min" | "short") {
+                    cmds.retain(|cmd| mode == cmd.full || mode == cmd.short);
+                    if cmds.is_empty() {
+                        self.err(format!("No help found for '{}'.", mode).as_str());
+                        return;
+                    }
+                }
+                self.emit(SUCCESS, "", cmds);
+                return;
             }
             if mode == "full" {
                 println!("Available commands:");
-                let mut cmds: Vec<(&CmdName, &CmdMeta)> = self.meta.iter().collect();
-                cmds.sort_by_key(|(_, cmd)| cmd.full);
-                for (_, cmd) in cmds {
+                for cmd in cmds {
                     print_full(cmd);
                 }
                 return;
             }
             if mode == "min" {
                 println!("Available commands:");
-                let mut cmds: Vec<(&CmdName, &CmdMeta)> = self.meta.iter().collect();
-                cmds.sort_by_key(|(_, cmd)| cmd.full);
-                for (_, cmd) in cmds {
+                for cmd in cmds {
                     print!("{} ", cmd.full);
                 }
                 println!();
@@ -1204,9 +1051,7 @@ mod cli {
             }
             if mode == "short" {
                 println!("Available commands:");
-                let mut cmds: Vec<(&CmdName, &CmdMeta)> = self.meta.iter().collect();
-                cmds.sort_by_key(|(_, cmd)| cmd.full);
-                for (_, cmd) in cmds {
+                for cmd in cmds {
                     println!("{} ", cmd.full);
                 }
                 return;
@@ -1214,34 +1059,37 @@ mod cli {
             // Check if mode matches a command
             // If so, print detailed help for that command
             // Otherwise, print error
-            for (_, cmd) in self.meta.iter() {
-                if mode == cmd.full || mode == cmd.short {
-                    print_full(cmd);
-                    return;
-                }
+            match find_cmd(mode) {
+                Some(cmd) => print_full(cmd),
+                None => println!("No help found for '{}'.", mode),
             }
-            println!("No help found for '{}'.", mode);
         }
 
-        pub fn target(&mut self, cmd: Vec<&str>, entities: &mut EntityList) {
-            CLI::cli_header("Target");
-            let ship = entities.get_player_mut().unwrap();
-            if cmd.len() < 2 {
+        pub fn target(&mut self, args: &Args, entities: &mut EntityList) {
+            self.cli_header("Target");
+            let ship = entities.get_player().unwrap();
+            let Some(ent_id) = args.int("ent_id") else {
                 if let Some(target_id) = ship.targeting_id {
+                    if self.json {
+                        self.emit(SUCCESS, "", json!({ "target_id": target_id }));
+                        return;
+                    }
                     println!("Current target ID: {}", target_id);
                 } else {
                     self.err("No target set.");
                 }
                 return;
-            }
-            let ent_id: i32 = match cmd[1].parse() {
-                Ok(num) => num,
-                Err(_) => {
-                    self.err("Invalid entity ID.");
-                    return;
-                }
             };
-            self.gm.set_target(ship, ent_id);
+            if let Err(e) = self.gm.set_target(entities, ent_id) {
+                self.gm_err("", &e);
+                return;
+            }
+            if self.json {
+                let msg = format!("Target set to entity ID {}", ent_id);
+                self.emit(SUCCESS, &msg, json!({ "target_id": ent_id }));
+                self.set_last_id(ent_id);
+                return;
+            }
 
             self.print_ent_line(ent_id, entities);
             println!("Target set to entity ID {}", ent_id);
@@ -1249,35 +1097,22 @@ mod cli {
             self.set_last_id(ent_id);
         }
 
-        pub fn scan(&mut self, cmd: Vec<&str>, entities: &EntityList) {
-            CLI::cli_header("Scan Report");
-            let mut scan_target = entities.get_player().unwrap();
-            if cmd.len() == 1 {
-                scan_target = entities.get_player().unwrap();
-            } else if cmd.len() == 2 {
-                let scan_target_id: i32 = match cmd[1].parse() {
-                    Ok(num) => num,
-                    Err(_) => {
-                        self.err("Invalid entity ID.");
-                        return;
-                    }
-                };
-                if let Some(_ent) = entities.get_by_id(scan_target_id) {
-                    scan_target = _ent;
-                } else {
-                    self.err(format!("No entity found with ID {}.", scan_target_id).as_str());
-                }
-            } else {
-                self.err("Usage: scan [<entity_id>]");
+        pub fn scan(&mut self, args: &Args, entities: &EntityList) {
+            self.cli_header("Scan Report");
+            let Some(scan_target) = self.ent_or_player(args, entities) else {
+                return;
+            };
+
+            if self.json {
+                self.emit(SUCCESS, "", scan_target);
+                self.set_last_id(scan_target.id);
+                return;
             }
 
             self.print_ent_line(scan_target.id, entities);
 
             // Get the current target of the scan target
-            let targeting_id = match scan_target.targeting_id {
-                Some(id) => id,
-                None => -1,
-            };
+            let targeting_id = scan_target.targeting_id.unwrap_or(-1);
             let targeting_str = if targeting_id == -1 {
                 "None".to_string()
             } else {
@@ -1285,10 +1120,7 @@ mod cli {
             };
 
             // Check if the scan target is docked
-            let docked_id = match scan_target.docked_id {
-                Some(id) => id,
-                none => -1,
-            };
+            let docked_id = scan_target.docked_id.unwrap_or(-1);
             let docked_str = if docked_id == -1 {
                 "None".to_string()
             } else {
@@ -1297,6 +1129,9 @@ mod cli {
 
             println!("{:<12}: {}", "Name", scan_target.name);
             println!("{:<12}: {:?}", "Class", scan_target.class);
+            if let Some(station_type) = scan_target.station_type {
+                println!("{:<12}: {}", "Type", station_type);
+            }
             println!(
                 "{:<12}: {}",
                 "Credits",
@@ -1310,291 +1145,272 @@ mod cli {
             self.set_last_id(scan_target.id);
         }
 
-        pub fn jump(&mut self, cmd: Vec<&str>, entities: &mut EntityList) {
-            CLI::cli_header("Jump");
-            if cmd.len() < 2 {
-                self.err("Usage: jump <entity_id>");
-                return;
-            }
-            let ent_id: i32 = match cmd[1].parse() {
-                Ok(num) => num,
-                Err(_) => {
-                    self.err("Invalid entity ID.");
-                    return;
-                }
-            };
+        pub fn jump(&mut self, args: &Args, entities: &mut EntityList) {
+            self.cli_header("Jump");
+            let ent_id = args.int("ent_id").unwrap();
             let ent_pos = if let Some(target) = entities.get_by_id(ent_id) {
-                target.pos.clone()
+                target.pos
             } else {
-                self.err(format!("No entity found with ID {}.", ent_id).as_str());
+                self.gm_err("", &GMError::UnknownEntity { ent_id });
                 return;
             };
 
             self.print_ent_line(ent_id, entities);
 
-            self._jump(entities.get_player_mut().unwrap(), &ent_pos);
+            self._jump(entities, &ent_pos);
 
             self.set_last_id(ent_id);
         }
 
-        pub fn jump_check(&mut self, cmd: Vec<&str>, entities: &EntityList) {
-            CLI::cli_header("Jump Check");
-            if cmd.len() < 2 {
-                self.err("Usage: jump_check <entity_id>");
-                return;
-            }
-            let ent_id: i32 = match cmd[1].parse() {
-                Ok(num) => num,
-                Err(_) => {
-                    self.err("Invalid entity ID.");
-                    return;
-                }
-            };
+        pub fn jump_check(&mut self, args: &Args, entities: &EntityList) {
+            self.cli_header("Jump Check");
+            let ent_id = args.int("ent_id").unwrap();
             let target_pos = if let Some(target) = entities.get_by_id(ent_id) {
-                target.pos.clone()
+                target.pos
             } else {
-                println!("No entity found with ID {}.", ent_id);
+                self.gm_err("", &GMError::UnknownEntity { ent_id });
                 return;
             };
             let ship = entities.get_player().unwrap();
             let res = self.gm.jump_check(ship, &target_pos);
             self.print_ent_line(ent_id, entities);
-            self.suc("Jump Check complete.");
-            println!("Distance: {} ly", res.distance);
-            println!("Fuel needed: {}g", res.fuel_needed);
-            println!("Current fuel: {}g", res.fuel_cur);
-            println!("Fuel after jump: {}g", res.fuel_after);
-            if res.can_jump {
-                self.suc("Jump is possible.");
-            } else {
-                self.err("Jump is NOT possible.");
-            }
+            self.report_jump_check(&res);
 
             self.set_last_id(ent_id);
         }
 
-        pub fn jump_man(&mut self, cmd: Vec<&str>, entities: &mut EntityList) {
-            CLI::cli_header("Jump (Manual)");
-            if cmd.len() < 2 {
-                self.err("Usage: jump_man <x> <y>");
-                return;
-            }
-            let x: i32 = match cmd[1].parse() {
-                Ok(num) => num,
-                Err(_) => {
-                    self.err("Invalid x coordinate.");
-                    return;
-                }
-            };
-            let y: i32 = match cmd[2].parse() {
-                Ok(num) => num,
-                Err(_) => {
-                    self.err("Invalid y coordinate.");
-                    return;
-                }
-            };
-            let destination = Position::new(x, y);
-            self._jump(entities.get_player_mut().unwrap(), &destination);
+        pub fn jump_man(&mut self, args: &Args, entities: &mut EntityList) {
+            self.cli_header("Jump (Manual)");
+            let destination = CLI::destination(args, None);
+            self._jump(entities, &destination);
         }
 
-        pub fn jump_check_man(&self, cmd: Vec<&str>, entities: &EntityList) {
-            CLI::cli_header("Jump Check (Manual)");
-            if cmd.len() < 3 {
-                self.err("Usage: jump_check <x> <y>");
-                return;
-            }
-            let x: i32 = match cmd[1].parse() {
-                Ok(num) => num,
-                Err(_) => {
-                    self.err("Invalid x coordinate.");
-                    return;
-                }
-            };
-            let y: i32 = match cmd[2].parse() {
-                Ok(num) => num,
-                Err(_) => {
-                    self.err("Invalid y coordinate.");
-                    return;
-                }
-            };
-            let destination = Position::new(x, y);
-            self.gm
+        pub fn jump_check_man(&self, args: &Args, entities: &EntityList) {
+            self.cli_header("Jump Check (Manual)");
+            let destination = CLI::destination(args, None);
+            let res = self
+                .gm
                 .jump_check(entities.get_player().unwrap(), &destination);
+            self.report_jump_check(&res);
         }
 
-        pub fn jump_rel(&mut self, cmd: Vec<&str>, entities: &mut EntityList) {
-            CLI::cli_header("Jump (Relative)");
-            if cmd.len() < 3 {
-                self.err("Usage: jump_rel <dx> <dy>");
-                return;
-            }
-            let dx: i32 = match cmd[1].parse() {
-                Ok(num) => num,
-                Err(_) => {
-                    self.err("Invalid x delta.");
-                    return;
-                }
-            };
-            let dy: i32 = match cmd[2].parse() {
-                Ok(num) => num,
-                Err(_) => {
-                    self.err("Invalid y delta.");
-                    return;
-                }
-            };
-            let ship = entities.get_player_mut().unwrap();
-            let destination = Position::new(ship.pos.x + dx, ship.pos.y + dy);
-            self._jump(ship, &destination);
+        pub fn jump_rel(&mut self, args: &Args, entities: &mut EntityList) {
+            self.cli_header("Jump (Relative)");
+            let ship = entities.get_player().unwrap();
+            let destination = CLI::destination(args, Some(ship.pos));
+            self._jump(entities, &destination);
         }
 
-        pub fn jump_check_rel(&self, cmd: Vec<&str>, entities: &EntityList) {
-            CLI::cli_header("Jump Check (Relative)");
-            if cmd.len() < 3 {
-                self.err("Usage: jump_check_rel <dx> <dy>");
-                return;
-            }
-            let dx: i32 = match cmd[1].parse() {
-                Ok(num) => num,
-                Err(_) => {
-                    self.err("Invalid x delta.");
-                    return;
-                }
-            };
-            let dy: i32 = match cmd[2].parse() {
-                Ok(num) => num,
-                Err(_) => {
-                    self.err("Invalid y delta.");
-                    return;
-                }
-            };
+        pub fn jump_check_rel(&self, args: &Args, entities: &EntityList) {
+            self.cli_header("Jump Check (Relative)");
             let ship = entities.get_player().unwrap();
-            let destination = Position::new(ship.pos.x + dx, ship.pos.y + dy);
-            self.gm.jump_check(ship, &destination);
+            let destination = CLI::destination(args, Some(ship.pos));
+            let res = self.gm.jump_check(ship, &destination);
+            self.report_jump_check(&res);
         }
 
-        pub fn cargo(&mut self, cmd: Vec<&str>, entities: &EntityList) {
-            CLI::cli_header("Cargo Hold");
-
-            // Default to player entity
-            let mut ent: &Entity = entities.get_player().unwrap();
-
-            // Override with specified entity ID
-            if cmd.len() > 1 {
-                let ent_id: i32 = match cmd[1].parse() {
-                    Ok(num) => num,
-                    Err(_) => {
-                        self.err("Invalid entity ID.");
-                        return;
-                    }
-                };
-                if let Some(_ent) = entities.get_by_id(ent_id) {
-                    ent = _ent;
-                } else {
-                    self.err(format!("No entity found with ID {}.", ent_id).as_str());
-                }
-            }
+        pub fn cargo(&mut self, args: &Args, entities: &EntityList) {
+            self.cli_header("Cargo Hold");
+            let Some(ent) = self.ent_or_player(args, entities) else {
+                return;
+            };
 
             self.print_ent_line(ent.id, entities);
 
             let hold = &ent.hold;
-            println!("Volume: {}/{}", hold.vol, hold.vol_max);
-            println!("-------");
-
+            // Only stations quote prices, other cargo is just listed
+            let is_station = ent.class == EntityClass::Station;
             // Sort alphabetically by fname
-            let mut items: Vec<(&ItemName, &i32)> = hold.inv.items();
+            let mut items: Vec<(&ItemId, &i32)> = hold.inv.items();
             items.sort_by_key(|(item, _)| {
-                let meta = ILM.get_by_enum(item).unwrap();
+                let meta = ILM.get(item).unwrap();
                 meta.fname.clone()
             });
-            let prices = &ent.fin.prices;
+            if self.json {
+                let rows: Vec<_> = items
+                    .iter()
+                    .map(|(item, qty)| {
+                        let meta = ILM.get(item).unwrap();
+                        let quoted = is_station && market::deals_in(ent, item);
+                        json!({
+                            "item": item,
+                            "name": meta.fname,
+                            "qty": qty,
+                            "traded": quoted,
+                            "bid": quoted.then(|| ent.fin.bid(item)),
+                            "ask": quoted.then(|| ent.fin.ask(item)),
+                        })
+                    })
+                    .collect();
+                let data = json!({
+                    "ent_id": ent.id,
+                    "vol": hold.vol,
+                    "vol_max": hold.vol_max,
+                    "items": rows,
+                });
+                self.emit(SUCCESS, "", data);
+                self.set_last_id(ent.id);
+                return;
+            }
+            println!("Volume: {}/{}", hold.vol, hold.vol_max);
+            println!("-------");
+            // Bid is what this entity pays, ask is what it charges
+            if is_station {
+                println!("{:<29}: {:>8} - Bid / Ask", "Item", "Stock");
+            } else {
+                println!("{:<29}: {:>8}", "Item", "Stock");
+            }
             for (item, qty) in items {
-                let meta = ILM.get_by_enum(item).unwrap();
-                let price = prices.get(item).unwrap_or(&0);
+                let meta = ILM.get(item).unwrap();
+                if !is_station {
+                    println!(
+                        "{}|{:<24}: {:>8}",
+                        meta.sname.to_uppercase().green(),
+                        meta.fname,
+                        fmt::peice(qty)
+                    );
+                    continue;
+                }
+                if !market::deals_in(ent, item) {
+                    println!(
+                        "{}|{:<24}: {:>8} - not traded",
+                        meta.sname.to_uppercase().green(),
+                        meta.fname,
+                        "-"
+                    );
+                    continue;
+                }
                 println!(
-                    "{}|{:<24}: {:>8} - {}",
+                    "{}|{:<24}: {:>8} - {} / {}",
                     meta.sname.to_uppercase().green(),
                     meta.fname,
                     fmt::peice(qty),
-                    fmt::credit(price)
+                    fmt::credit(&ent.fin.bid(item)),
+                    fmt::credit(&ent.fin.ask(item))
                 );
             }
 
             self.set_last_id(ent.id);
         }
 
-        pub fn buy(&mut self, cmd: Vec<&str>, entities: &mut EntityList) {
-            CLI::cli_header("Buy Items");
-            if cmd.len() < 4 {
-                self.err("Usage: buy <ent_id> <item> <qty>");
-                return;
-            }
-            let ent_id: i32 = match cmd[1].parse() {
-                Ok(num) => num,
-                Err(_) => {
-                    self.err("Invalid entity ID.");
-                    return;
-                }
-            };
-            let item = match ILM.get_by_sname(cmd[2]) {
-                Some(it) => it,
-                None => {
-                    self.err(format!("Invalid item sname: '{}'", cmd[2]).as_str());
+        pub fn buy(&mut self, args: &Args, entities: &mut EntityList) {
+            self.cli_header("Buy Items");
+            let ent_id = args.int("ent_id").unwrap();
+            let item = args.item("item").unwrap();
+            let qty = args.int("qty").unwrap();
+            let player_id = entities.get_player().unwrap().id;
+            let res = self.gm.buy(entities, player_id, ent_id, &item, qty);
+            let trade = match res {
+                Ok(trade) => trade,
+                Err(e) => {
+                    self.gm_err("Purchase failed", &e);
                     return;
                 }
             };
-            let qty: i32 = match cmd[3].parse() {
-                Ok(num) => num,
-                Err(_) => {
-                    self.err("Invalid quantity.");
+            let meta = ILM.get(&item).unwrap();
+            if self.json {
+                let data = json!({
+                    "ent_id": ent_id,
+                    "item": item,
+                    "qty": trade.qty,
+                    "price": trade.price,
+                    "total": trade.total,
+                    "credits": entities.get_player().unwrap().fin.credits,
+                });
+                let msg = format!("Bought {} {} for {} credits", qty, meta.sname, trade.total);
+                self.emit(SUCCESS, &msg, data);
+                self.set_last_id(ent_id);
+                return;
+            }
+            let seller = entities.get_by_id(ent_id).unwrap();
+            self.suc("Purchase successful.");
+            println!(
+                "Bought {} of {} from {}.",
+                fmt::peice(&qty),
+                meta.fname,
+                seller.name
+            );
+            println!(
+                "Paid {} ({} each). Credits left: {}",
+                fmt::credit(&trade.total),
+                fmt::credit(&trade.price),
+                fmt::credit(&entities.get_player().unwrap().fin.credits)
+            );
+            self.set_last_id(ent_id);
+        }
+
+        pub fn sell(&mut self, args: &Args, entities: &mut EntityList) {
+            self.cli_header("Sell Items");
+            let ent_id = args.int("ent_id").unwrap();
+            let item = args.item("item").unwrap();
+            let qty = args.int("qty").unwrap();
+            let player_id = entities.get_player().unwrap().id;
+            let res = self.gm.sell(entities, player_id, ent_id, &item, qty);
+            let trade = match res {
+                Ok(trade) => trade,
+                Err(e) => {
+                    self.gm_err("Sale failed", &e);
                     return;
                 }
             };
-            // Check distance from player to ent, must be within 1 ly
-            let player = entities.get_player().unwrap();
-            let seller = entities.get(ent_id).unwrap();
-            let res = self.gm.buy(entities, player.id, seller.id, item, qty);
-            match res.status {
-                GMRS::Success => {
-                    self.suc("Purchase successful.");
-                    println!(
-                        "Bought {} of {} from {}.",
-                        fmt::peice(&qty),
-                        item.fname,
-                        seller.name
-                    );
-                }
-                GMRS::TooFar => {
-                    self.err("Too far to trade");
-                }
-                GMRS::NotDocked => {
-                    self.err("Must be docked to trade with stations.");
-                }
-                _ => {
-                    self.err(format!("{}", res.message).as_str());
-                }
+            let meta = ILM.get(&item).unwrap();
+            if self.json {
+                let data = json!({
+                    "ent_id": ent_id,
+                    "item": item,
+                    "qty": trade.qty,
+                    "price": trade.price,
+                    "total": trade.total,
+                    "credits": entities.get_player().unwrap().fin.credits,
+                });
+                let msg = format!("Sold {} {} for {} credits", qty, meta.sname, trade.total);
+                self.emit(SUCCESS, &msg, data);
+                self.set_last_id(ent_id);
+                return;
             }
+            let buyer = entities.get_by_id(ent_id).unwrap();
+            self.suc("Sale successful.");
+            println!(
+                "Sold {} of {} to {}.",
+                fmt::peice(&qty),
+                meta.fname,
+                buyer.name
+            );
+            println!(
+                "Received {} ({} each). Credits now: {}",
+                fmt::credit(&trade.total),
+                fmt::credit(&trade.price),
+                fmt::credit(&entities.get_player().unwrap().fin.credits)
+            );
+            self.set_last_id(ent_id);
         }
 
-        pub fn sell(&mut self, cmd: Vec<&str>, entities: &mut EntityList) {
-            CLI::cli_header("Sell Items");
-        }
-
-        pub fn entities(&self, cmd: Vec<&str>, entities: &EntityList) {
-            CLI::cli_header("Entities List");
-            let max_distance: i32;
-            if cmd.len() < 2 {
-                max_distance = entities.get_player().unwrap().jump_drive.max_range;
-            } else {
-                max_distance = match cmd[1].parse() {
-                    Ok(num) => num,
-                    Err(_) => {
-                        self.err("Invalid max distance.");
-                        return;
-                    }
-                };
-            }
+        pub fn entities(&self, args: &Args, entities: &EntityList) {
+            self.cli_header("Entities List");
+            let max_distance = args
+                .int("dist")
+                .unwrap_or(entities.get_player().unwrap().jump_drive.max_range);
             let mut found = 0;
             let ent = entities.get_player().unwrap();
+            if self.json {
+                let rows: Vec<_> = entities
+                    .list_by_distance(ent.pos, max_distance)
+                    .iter()
+                    .map(|target| {
+                        json!({
+                            "id": target.id,
+                            "class": target.class,
+                            "station_type": target.station_type,
+                            "distance": ent.pos.distance(&target.pos),
+                            "pos": target.pos,
+                            "name": target.name,
+                        })
+                    })
+                    .collect();
+                let msg = format!("Found {} entities within {} ly", rows.len(), max_distance);
+                self.emit(SUCCESS, &msg, rows);
+                return;
+            }
             entities
                 .list_by_distance(ent.pos, max_distance)
                 .iter()
@@ -1603,6 +1419,7 @@ mod cli {
                         EntityClass::Station => "STAT",
                         EntityClass::Craft => "CRFT",
                     };
+                    let type_str = target.station_type.map(|t| t.code()).unwrap_or("-");
                     let distance = ent.pos.distance(&target.pos);
                     if distance == 0 {
                         print!("^^ ");
@@ -1610,313 +1427,1554 @@ mod cli {
                         print!("<< ");
                     }
                     println!(
-                        "{:<6}: [{}] {:>5} ly - ({:>5}, {:>5}) - {}",
-                        target.id, class_str, distance, target.pos.x, target.pos.y, target.name
+                        "{:<6}: [{}|{:<4}] {:>5} ly - ({:>5}, {:>5}) - {}",
+                        target.id,
+                        class_str,
+                        type_str,
+                        distance,
+                        target.pos.x,
+                        target.pos.y,
+                        target.name
                     );
                     found += 1;
                 });
             println!("Found {} entities within {} ly", found, max_distance);
         }
 
-        pub fn dock_list(&self, cmd: Vec<&str>, entities: &EntityList) {
-            CLI::cli_header("Dock List");
+        pub fn dock_list(&self, entities: &EntityList) {
+            self.cli_header("Dock List");
+            let stations = self.gm.dock_list(entities.get_player().unwrap(), entities);
+            if self.json {
+                let pos = entities.get_player().unwrap().pos;
+                let rows: Vec<_> = stations
+                    .iter()
+                    .map(|ent| {
+                        json!({
+                            "id": ent.id,
+                            "name": ent.name,
+                            "distance": pos.distance(&ent.pos),
+                        })
+                    })
+                    .collect();
+                self.emit(SUCCESS, "", rows);
+                return;
+            }
             println!("Nearby docking-capable entities:");
-            let res = self.gm.dock_list(entities.get_player().unwrap(), &entities);
-            if res.entities.is_empty() {
+            if stations.is_empty() {
                 self.err("No docking-capable entities nearby.");
             } else {
-                for ent in res.entities {
+                for ent in stations {
                     let distance = entities.get_player().unwrap().pos.distance(&ent.pos);
                     println!("ID {}: {} ({} ly away)", ent.id, ent.name, distance);
                 }
             }
         }
 
-        pub fn dock(&mut self, cmd: Vec<&str>, entities: &mut EntityList) {
-            CLI::cli_header("Docking");
-            if cmd.len() < 2 {
-                self.err("Usage: dock <entity_id>");
+        pub fn dock(&mut self, args: &Args, entities: &mut EntityList) {
+            self.cli_header("Docking");
+            let ent_id = args.int("ent_id").unwrap();
+            if let Err(e) = self.gm.dock(entities, ent_id) {
+                self.gm_err("Docking failed", &e);
                 return;
             }
-            let ent_id: i32 = match cmd[1].parse() {
-                Ok(num) => num,
-                Err(_) => {
-                    self.err("Invalid entity ID.");
-                    return;
-                }
-            };
-
-            self.print_ent_line(ent_id, entities);
-
-            let res = self.gm.dock(entities, ent_id);
-            if res.status == GMRS::Success {
-                self.suc(format!("Docked to: {}", ent_id).as_str());
+            if self.json {
+                let name = &entities.get_by_id(ent_id).unwrap().name;
+                let msg = format!("Docked with {}.", name);
+                self.emit(SUCCESS, &msg, json!({ "ent_id": ent_id }));
             } else {
-                self.err(format!("Docking failed: {}", res.message).as_str());
+                self.print_ent_line(ent_id, entities);
+                self.suc(format!("Docked to: {}", ent_id).as_str());
             }
 
             self.set_last_id(ent_id);
         }
 
-        pub fn undock(&self, cmd: Vec<&str>, entities: &mut EntityList) {
-            CLI::cli_header("Undocking");
-            self.gm.undock(entities.get_player_mut().unwrap());
+        pub fn undock(&self, entities: &mut EntityList) {
+            self.cli_header("Undocking");
+            let player_id = entities.get_player().unwrap().id;
+            match self.gm.undock(entities, player_id) {
+                Ok(station_id) if self.json => {
+                    let msg = format!("Undocked from entity ID {}.", station_id);
+                    self.emit(SUCCESS, &msg, json!({ "ent_id": station_id }));
+                }
+                Ok(station_id) => {
+                    self.suc(format!("Undocked from entity ID {}.", station_id).as_str());
+                }
+                Err(e) => self.gm_err("", &e),
+            }
+        }
+
+        pub fn name(&self, args: &Args, entities: &mut EntityList) {
+            self.cli_header("Rename Ship");
+            let new_name = args.word("new_name").unwrap();
+            let player_id = entities.get_player().unwrap().id;
+            if let Err(e) = self.gm.name_ent(entities, player_id, new_name) {
+                self.gm_err("", &e);
+                return;
+            }
+            let msg = format!("Renamed to {}", new_name);
+            if self.json {
+                self.emit(SUCCESS, &msg, json!({ "name": new_name }));
+            } else {
+                self.suc(&msg);
+            }
         }
 
-        pub fn name(&self, cmd: Vec<&str>, entities: &mut EntityList) {
-            CLI::cli_header("Rename Ship");
-            if cmd.len() < 2 {
-                self.err("Usage: name <new_name>");
+        pub fn time(&self) {
+            self.cli_header("Game Time");
+            if self.json {
+                // Seed is null when an old save never recorded it
+                let data = json!({ "tick": self.gm.tick(), "seed": self.gm.seed() });
+                self.emit(SUCCESS, "", data);
                 return;
             }
-            let new_name = cmd[1..].join(" ");
-            self.gm
-                .name_ent(entities.get_player_mut().unwrap(), &new_name);
+            println!("Current game tick: {}", self.gm.tick());
+            match self.gm.seed() {
+                Some(seed) => println!("Galaxy seed: {}", seed),
+                None => println!("Galaxy seed: unknown"),
+            }
         }
 
-        pub fn time(&self, cmd: Vec<&str>) {
-            CLI::cli_header("Game Time");
-            println!("Current game tick: {}", self.gm.tick);
+        pub fn wait(&mut self, args: &Args, entities: &mut EntityList) {
+            self.cli_header("Wait");
+            let ticks = args.int("ticks").unwrap_or(1);
+            self.gm.advance(entities, ticks);
+            if self.json {
+                let msg = format!("Waited {} ticks.", ticks);
+                self.emit(SUCCESS, &msg, json!({ "tick": self.gm.tick() }));
+                return;
+            }
+            self.suc(format!("Waited {} ticks.", ticks).as_str());
+            println!("Current game tick: {}", self.gm.tick());
         }
 
-        pub fn refuel(&self, cmd: Vec<&str>, entities: &mut EntityList) {
-            CLI::cli_header("Refuel Ship");
-            let ship = entities.get_player_mut().unwrap();
-            // Check if docked
-            if ship.docked_id.is_none() {
-                self.err("Must be docked to refuel.");
+        pub fn refuel(&self, entities: &mut EntityList) {
+            self.cli_header("Refuel Ship");
+            let ship = entities.get_player().unwrap();
+            let (player_id, amount) = (ship.id, ship.jump_drive.refuel_amt());
+            let refuel = match self.gm.refuel(entities, player_id, amount) {
+                Ok(refuel) => refuel,
+                Err(e) => {
+                    self.gm_err("", &e);
+                    return;
+                }
+            };
+            let ship = entities.get_player().unwrap();
+            let msg = format!("Refueled {} g for {} credits.", refuel.amount, refuel.cost);
+            if self.json {
+                let data = json!({
+                    "amount": refuel.amount,
+                    "cost": refuel.cost,
+                    "wanted": refuel.wanted,
+                    "fuel_cur": ship.jump_drive.fuel_cur,
+                    "fuel_max": ship.jump_drive.fuel_max,
+                    "credits": ship.fin.credits,
+                });
+                self.emit(SUCCESS, &msg, data);
                 return;
             }
-            let mut amt_needed = ship.jump_drive.refuel_amt();
-            if amt_needed == 0 {
-                self.err("Jump drive is already full.");
+            if refuel.amount < refuel.wanted {
+                self.err("Not enough credits to refuel completely.");
+                println!("You could only afford to refuel {} g.", refuel.amount);
+            } else {
+                self.suc(&msg);
+            }
+            println!("Current fuel: {}", ship.jump_drive.fuel_str());
+        }
+
+        pub fn route(&mut self, args: &Args, entities: &EntityList) {
+            self.cli_header("Route Planner");
+            let ent_id = args.int("ent_id").unwrap();
+            let mode = args
+                .word("mode")
+                .and_then(RouteMode::parse)
+                .unwrap_or(RouteMode::Fuel);
+            let player_id = entities.get_player().unwrap().id;
+            let res = self.gm.plan_route(entities, player_id, ent_id, mode);
+            // An unaffordable route is still worth showing
+            let route = match &res {
+                Ok(route) => route,
+                Err(GMError::RouteUnaffordable { route, .. }) if !self.json => route,
+                Err(e) => {
+                    self.gm_err("", e);
+                    return;
+                }
+            };
+            let msg = format!("Route found with {} hops.", route.hops.len());
+            if self.json {
+                self.emit(SUCCESS, &msg, route);
+                self.set_last_id(ent_id);
                 return;
             }
-            let cost_per_g = 0.2; // Example cost
-            let mut total_cost = (amt_needed as f32 * cost_per_g) as i32;
-            if ship.fin.credits < total_cost {
-                self.err(
+
+            self.print_ent_line(ent_id, entities);
+            for (i, hop) in route.hops.iter().enumerate() {
+                let ent = entities.get_by_id(hop.ent_id).unwrap();
+                let refuel_str = if hop.refuel > 0 {
                     format!(
-                        "Not enough credits to refuel completely. Need {}, have {}.",
-                        total_cost, ship.fin.credits
+                        " + refuel {} for {}",
+                        fmt::fuel(&hop.refuel),
+                        fmt::credit(&hop.refuel_cost)
                     )
-                    .as_str(),
+                } else {
+                    String::new()
+                };
+                println!(
+                    "{:>3}. {:<6} {:>5} ly {:>6} g - {}{}",
+                    i + 1,
+                    hop.ent_id,
+                    hop.distance,
+                    hop.fuel,
+                    ent.name,
+                    refuel_str
                 );
-                amt_needed = (ship.fin.credits as f32 / cost_per_g) as i32;
-                println!("You can only afford to refuel {} g.", amt_needed);
-                total_cost = ship.fin.credits;
-            }
-            ship.fin.credits -= total_cost;
-            ship.jump_drive.refuel(amt_needed);
-            self.err(
-                format!(
-                    "Refueled {} g for {} credits. Current fuel: {}",
-                    amt_needed,
-                    total_cost,
-                    ship.jump_drive.fuel_str()
-                )
-                .as_str(),
+            }
+            println!("-------");
+            println!("Hops: {}", route.hops.len());
+            println!("Total distance: {}", fmt::distance(&route.distance));
+            println!("Total fuel: {}", fmt::fuel(&route.fuel));
+            println!(
+                "Refuel stops: {} costing {}",
+                route.refuel_stops,
+                fmt::credit(&route.cost)
             );
-        }
+            match &res {
+                Ok(_) => self.suc(&msg),
+                Err(e) => self.err(&e.to_string()),
+            }
 
-        pub fn save(&self, cmd: Vec<&str>, entities: &EntityList) {
-            CLI::cli_header("Save Game");
-            let filename = if cmd.len() < 2 {
-                "savegame.json"
-            } else {
-                cmd[1]
-            };
-            self.gm.save(&entities, filename);
-            self.suc("Saved!");
-            println!("Game saved to {}", filename);
+            self.set_last_id(ent_id);
         }
 
-        pub fn load(&self, cmd: Vec<&str>, entities: &mut EntityList) {
-            CLI::cli_header("Load Game");
-            let filename = if cmd.len() < 2 {
-                "savegame.json"
-            } else {
-                cmd[1]
+        pub fn travel(&mut self, args: &Args, entities: &mut EntityList) {
+            self.cli_header("Autopilot");
+            let ent_id = args.int("ent_id").unwrap();
+            let mode = args
+                .word("mode")
+                .and_then(RouteMode::parse)
+                .unwrap_or(RouteMode::Fuel);
+
+            self.print_ent_line(ent_id, entities);
+            let res = self.gm.travel(entities, ent_id, mode);
+            let legs = match &res {
+                Ok(legs) | Err(GMError::Stopped { legs, .. }) => legs.as_slice(),
+                Err(_) => &[],
             };
-            self.gm.load(entities, filename);
-            self.suc("Loaded!");
-            println!("Loaded game from {}", filename);
-        }
+            if self.json {
+                let ship = entities.get_player().unwrap();
+                let data = json!({
+                    "legs": legs,
+                    "pos": ship.pos,
+                    "fuel_cur": ship.jump_drive.fuel_cur,
+                    "fuel_max": ship.jump_drive.fuel_max,
+                    "credits": ship.fin.credits,
+                    "tick": self.gm.tick(),
+                });
+                match &res {
+                    Ok(_) => {
+                        let msg = format!("Arrived at entity ID {}.", ent_id);
+                        self.emit(SUCCESS, &msg, data);
+                    }
+                    Err(e) => self.emit(
+                        e.kind(),
+                        &e.to_string(),
+                        json!({ "error": e, "ship": data }),
+                    ),
+                }
+                self.set_last_id(ent_id);
+                return;
+            }
+            for (i, leg) in legs.iter().enumerate() {
+                if leg.refuel > 0 {
+                    println!(
+                        "     refueled {} for {}",
+                        fmt::fuel(&leg.refuel),
+                        fmt::credit(&leg.refuel_cost)
+                    );
+                }
+                if leg.distance > 0 || leg.fuel_used > 0 {
+                    let name = &entities.get_by_id(leg.ent_id).unwrap().name;
+                    println!(
+                        "{:>3}. {} {:>5} ly {:>6} g - {}",
+                        i + 1,
+                        ch::ARR,
+                        leg.distance,
+                        leg.fuel_used,
+                        name
+                    );
+                }
+            }
+            match &res {
+                Ok(_) => self.suc(format!("Arrived at entity ID {}.", ent_id).as_str()),
+                Err(e) => self.err(&e.to_string()),
+            }
+            let ship = entities.get_player().unwrap();
+            println!("Position: {}", ship.pos);
+            println!("Fuel: {}", ship.jump_drive.fuel_str());
+            println!("Credits: {}", fmt::credit(&ship.fin.credits));
+            println!("Game tick: {}", self.gm.tick());
 
-        pub fn quit(&self, cmd: Vec<&str>) {
-            CLI::cli_header("Goodbye");
-            self.err("Exiting...");
+            self.set_last_id(ent_id);
         }
 
-        pub fn unknown(&self, cmd: Vec<&str>) {
-            CLI::cli_header("Unknown Command");
-            self.err(format!("Unknown command: {}", cmd[0]).as_str());
+        // Slot names are the norm, anything that looks like a path is used as-is
+        fn is_path(arg: &str) -> bool {
+            arg.contains('/') || arg.contains('\\') || arg.ends_with(".json")
         }
 
-        fn _jump(&mut self, ent: &mut Entity, target: &Position) {
-            println!("Attempting jump to {}", target);
-            let res = self.gm.jump(ent, target);
-            if res.success {
-                self.suc("Jump successful");
-                println!("Distance traveled: {} ly", res.distance);
-                println!("Fuel used: {} g", res.fuel_used);
-                println!("Current Fuel: {}", ent.jump_drive.fuel_str());
-                println!("New Position: {}", ent.pos);
+        pub fn save(&self, args: &Args, entities: &EntityList) {
+            self.cli_header("Save Game");
+            let slot = args.word("slot").unwrap_or(DEFAULT_SLOT);
+            let res = if CLI::is_path(slot) {
+                self.gm.save(entities, &self.session, slot)
             } else {
-                self.err(format!("Jump failed: {}", res.message).as_str());
+                self.slots.save(&self.gm, entities, &self.session, slot)
+            };
+            match res {
+                Ok(path) if self.json => {
+                    let msg = format!("Saved to {}", path);
+                    self.emit(SUCCESS, &msg, json!({ "slot": slot, "path": path }));
+                }
+                Ok(path) => {
+                    self.suc("Saved!");
+                    println!("Saved to {}", path);
+                }
+                Err(e) => self.gm_err("Save failed", &e),
             }
         }
-        fn cli_header(title: &str) {
-            println!("{}", "▀".repeat(64).green());
-            println!(
-                "██▀{:^67}▄██",
-                format!("⏣ |⯟ |- {} -|⯟ |⏣", title.to_uppercase().bright_green())
-            );
-            println!("{}", "▄".repeat(64).green());
-            println!();
-        }
-    }
-}
 
-use crate::cli::CmdName;
-use crate::entity::Entity;
-use crate::entity::EntityClass;
-use crate::entity_list::EntityList;
-use crate::input::prompt;
-use crate::item_name::ItemName;
-use crate::pos::Position;
-use crate::univ::UNIV;
-use std::io::{self, Write};
+        pub fn load(&mut self, args: &Args, entities: &mut EntityList) {
+            self.cli_header("Load Game");
+            let slot = args.word("slot").unwrap_or(DEFAULT_SLOT);
+            match self.load_game(slot, entities) {
+                Ok(path) if self.json => {
+                    let msg = format!("Loaded from {}", path);
+                    self.emit(SUCCESS, &msg, json!({ "slot": slot, "path": path }));
+                }
+                Ok(path) => {
+                    self.suc("Loaded!");
+                    println!("Loaded from {}", path);
+                }
+                Err(e) => {
+                    self.gm_err("Load failed", &e);
+                    if !self.json {
+                        println!("The current game was kept.");
+                    }
+                }
+            }
+        }
 
-fn main() {
-    // Load names from file (res/names.txt)
-    let mut name_list: Vec<String> = Vec::new();
-    if let Ok(contents) = std::fs::read_to_string("res/names.txt") {
-        for line in contents.lines() {
-            name_list.push(line.to_string());
-        }
-    } else {
-        // Exit
-        println!("Error: Unable to read names.txt");
-        return;
-    }
+        // Slot name or file path, shared by `load` and --load
+        // Returns the file that was loaded
+        pub fn load_game(
+            &mut self,
+            slot: &str,
+            entities: &mut EntityList,
+        ) -> Result<String, GMError> {
+            let path = if CLI::is_path(slot) {
+                self.gm.load(entities, &mut self.session, slot)
+            } else {
+                self.slots
+                    .load(&mut self.gm, entities, &mut self.session, slot)
+            }?;
+            self.slots.reset_autosave(self.gm.tick());
+            Ok(path)
+        }
+
+        pub fn saves(&self) {
+            self.cli_header("Save Slots");
+            let slots = match self.slots.list() {
+                Ok(slots) => slots,
+                Err(e) => {
+                    self.gm_err("Could not list saves", &e);
+                    return;
+                }
+            };
+            if self.json {
+                let rows: Vec<_> = slots
+                    .iter()
+                    .map(|slot| match &slot.meta {
+                        Ok(meta) => json!({ "slot": slot.name, "meta": meta }),
+                        Err(e) => json!({ "slot": slot.name, "error": e.to_string() }),
+                    })
+                    .collect();
+                let data = json!({ "dir": self.slots.dir, "slots": rows });
+                self.emit(SUCCESS, "", data);
+                return;
+            }
+            println!("Directory: {}", self.slots.dir.display());
+            if slots.is_empty() {
+                println!("No saves yet.");
+                return;
+            }
+            println!(
+                "{:<16} {:<20} {:>10} {:>7}  Saved",
+                "Slot", "Ship", "Credits", "Tick"
+            );
+            for slot in slots {
+                match slot.meta {
+                    Ok(meta) => println!(
+                        "{:<16} {:<20} {:>10} {:>7}  {}",
+                        slot.name.green(),
+                        meta.ship_name,
+                        meta.credits,
+                        meta.tick,
+                        fmt::timestamp(&meta.saved_at)
+                    ),
+                    Err(e) => println!("{:<16} {}", slot.name.red(), e),
+                }
+            }
+        }
 
-    let mut entities = EntityList::new();
+        pub fn delete(&self, args: &Args) {
+            self.cli_header("Delete Save");
+            let slot = args.word("slot").unwrap();
+            match self.slots.delete(slot) {
+                Ok(_) if self.json => {
+                    let msg = format!("Deleted save slot '{}'.", slot);
+                    self.emit(SUCCESS, &msg, json!({ "slot": slot }));
+                }
+                Ok(_) => self.suc(format!("Deleted save slot '{}'.", slot).as_str()),
+                Err(e) => self.gm_err("Delete failed", &e),
+            }
+        }
 
-    let mut start_ship = Entity::new("Ferris 1");
-    start_ship.class = EntityClass::Craft;
-    start_ship.hold.insert(ItemName::MetalLow, 20);
-    start_ship.hold.insert(ItemName::CompositeMid, 15);
-    start_ship.hold.insert(ItemName::PolymerHigh, 5);
-    let gal_center = UNIV.gal_size / 2;
-    start_ship.set_pos(Position::new(gal_center, gal_center));
-    entities.add(start_ship.clone());
+        pub fn autosave(&mut self, args: &Args) {
+            self.cli_header("Autosave");
+            let Some(every) = args.int("ticks") else {
+                if self.json {
+                    self.emit_autosave("");
+                } else if self.slots.autosave_every > 0 {
+                    println!(
+                        "Autosaving every {} ticks, keeping {} files.",
+                        self.slots.autosave_every, self.slots.autosave_keep
+                    );
+                } else {
+                    println!("Autosave is off.");
+                }
+                return;
+            };
+            if let Some(keep) = args.int("keep") {
+                self.slots.autosave_keep = keep as usize;
+            }
+            self.slots.autosave_every = every;
+            self.slots.reset_autosave(self.gm.tick());
+            if self.json {
+                self.emit_autosave("Autosave updated.");
+            } else if every == 0 {
+                self.suc("Autosave turned off.");
+            } else {
+                self.suc(format!("Autosaving every {} ticks.", every).as_str());
+            }
+        }
 
-    entities.generate_entities(&name_list, UNIV.starting_entities as usize);
+        fn emit_autosave(&self, message: &str) {
+            let data = json!({
+                "every": self.slots.autosave_every,
+                "keep": self.slots.autosave_keep,
+            });
+            self.emit(SUCCESS, message, data);
+        }
 
-    let mut cli = cli::CLI::new();
+        // Called after every command, saves quietly unless something goes wrong
+        pub fn autosave_tick(&mut self, entities: &EntityList) {
+            if let Some(Err(e)) = self.slots.autosave(&self.gm, entities, &self.session) {
+                self.gm_err("Autosave failed", &e);
+            }
+        }
 
-    cli.intro();
+        // Run one line of input, shared by the prompt, --script and `run`
+        pub fn dispatch(&mut self, entities: &mut EntityList, line: &str) -> Flow {
+            let mut cmd_raw = line.trim().to_string();
+            // Replace "@" with current target ID
+            if let Some(target_id) = entities.get_player().unwrap().targeting_id {
+                cmd_raw = cmd_raw.replace("@", &target_id.to_string());
+            }
+            // Replace # with last used ID
+            if cmd_raw == "#" {
+                if self.json {
+                    self.command.set("#");
+                    self.emit(SUCCESS, "", json!({ "last_id": self.session.last_id }));
+                } else {
+                    println!("Last ID: {}", self.session.last_id);
+                }
+                return Flow::Continue;
+            }
+            cmd_raw = cmd_raw.replace("#", &self.session.last_id.to_string());
 
-    loop {
-        println!();
-        let mut cmd_raw = prompt();
-        // Replace "@" with current target ID
-        if let Some(target_id) = entities.get_player().unwrap().targeting_id {
-            cmd_raw = cmd_raw.replace("@", &target_id.to_string());
-        }
-        // Replace # with last used ID
-        if cmd_raw == "#" {
-            println!("Last ID: {}", cli.last_id);
-            continue;
+            // Split command into parts
+            let cmd: Vec<&str> = cmd_raw.split_whitespace().collect();
+            if cmd.is_empty() {
+                return Flow::Continue;
+            }
+            let Some(meta) = find_cmd(cmd[0]) else {
+                self.command.set("unknown");
+                self.unknown(cmd[0]);
+                self.autosave_tick(entities);
+                return Flow::Continue;
+            };
+            self.command.set(meta.full);
+
+            let flow = match meta.parse(&cmd[1..], self.gm.univ().gal_size) {
+                Ok(args) => (meta.run)(self, &args, entities),
+                Err(e) => {
+                    self.usage_err(meta, &e);
+                    Flow::Continue
+                }
+            };
+            if let Flow::Quit = flow {
+                return Flow::Quit;
+            }
+            self.autosave_tick(entities);
+            Flow::Continue
+        }
+
+        // Feed each line of a file to dispatch
+        // Blank lines and lines starting with # are skipped
+        pub fn run_script(
+            &mut self,
+            path: &str,
+            opts: &ScriptOpts,
+            entities: &mut EntityList,
+        ) -> Flow {
+            if self.script_depth >= MAX_SCRIPT_DEPTH {
+                self.err(format!("Scripts nested more than {} deep.", MAX_SCRIPT_DEPTH).as_str());
+                return Flow::Continue;
+            }
+            let contents = match std::fs::read_to_string(path) {
+                Ok(contents) => contents,
+                Err(e) => {
+                    self.err(format!("Unable to read {}: {}", path, e).as_str());
+                    return Flow::Continue;
+                }
+            };
+            self.script_depth += 1;
+            // Each line names itself, put back the caller's name afterwards
+            let command = self.command.get();
+            let mut flow = Flow::Continue;
+            let mut any_failed = false;
+            for (num, line) in contents.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                if opts.echo && !self.json {
+                    let prompt_str = format!("{} | ", ch::SP1).bright_green();
+                    println!("{}{}", prompt_str, line);
+                }
+                self.failed.set(false);
+                flow = self.dispatch(entities, line);
+                any_failed |= self.failed.get();
+                if let Flow::Quit = flow {
+                    break;
+                }
+                if self.failed.get() && opts.stop_on_fail {
+                    self.err(format!("Stopped at {} line {}.", path, num + 1).as_str());
+                    break;
+                }
+            }
+            self.script_depth -= 1;
+            self.command.set(command);
+            self.failed.set(any_failed);
+            flow
         }
-        cmd_raw = cmd_raw.replace("#", &cli.last_id.to_string());
 
-        // Split command into parts
-        let cmd: Vec<&str> = cmd_raw.split_whitespace().collect();
-        if cmd.is_empty() {
-            continue;
+        // Whether anything went wrong since the last command started
+        pub fn failed(&self) -> bool {
+            self.failed.get()
         }
 
-        // Clear screen
-        print!("\x1B[2J\x1B[1;1H");
-        // Flush stdout
-        io::stdout().flush().unwrap();
+        pub fn run(&mut self, args: &Args, entities: &mut EntityList) -> Flow {
+            self.cli_header("Run Script");
+            let file = args.word("file").unwrap();
+            let opts = ScriptOpts {
+                stop_on_fail: args.words("option").any(|opt| opt == "stop"),
+                echo: args.words("option").any(|opt| opt == "echo"),
+            };
+            let flow = self.run_script(file, &opts, entities);
+            if !self.failed() {
+                if self.json {
+                    let msg = format!("Finished {}", file);
+                    self.emit(SUCCESS, &msg, ());
+                }
+                self.suc(format!("Finished {}", file).as_str());
+            }
+            flow
+        }
 
-        // TODO:
-        // Use a command map to call functions dynamically
-        // This match is no good
-        match cmd[0] {
-            v if cli.check_cmd(v, CmdName::Help) => {
-                cli.help(cmd);
+        pub fn quit(&self) {
+            self.cli_header("Goodbye");
+            if self.json {
+                self.emit(SUCCESS, "Exiting...", ());
+                return;
             }
-            v if cli.check_cmd(v, CmdName::Target) => {
-                cli.target(cmd, &mut entities);
+            // Leaving isn't a failure, a script ending in quit still exits 0
+            self.suc("Exiting...");
+        }
+
+        pub fn unknown(&self, word: &str) {
+            self.cli_header("Unknown Command");
+            self.err(format!("Unknown command: {}", word).as_str());
+        }
+
+        // Shared by the jump_check commands
+        fn report_jump_check(&self, res: &JumpCheck) {
+            let verdict = if res.can_jump {
+                "Jump is possible."
+            } else {
+                "Jump is NOT possible."
+            };
+            if self.json {
+                match &res.blocked {
+                    Some(e) => self.emit(e.kind(), &e.to_string(), res),
+                    None => self.emit(SUCCESS, verdict, res),
+                }
+                return;
             }
-            v if cli.check_cmd(v, CmdName::Scan) => {
-                cli.scan(cmd, &entities);
+            self.suc("Jump Check complete.");
+            println!("Distance: {} ly", res.distance);
+            println!("Fuel needed: {}g", res.fuel_needed);
+            println!("Current fuel: {}g", res.fuel_cur);
+            println!("Fuel after jump: {}g", res.fuel_after);
+            match &res.blocked {
+                Some(e) => self.err(format!("{} {}", verdict, e).as_str()),
+                None => self.suc(verdict),
             }
-            v if cli.check_cmd(v, CmdName::Jump) => {
-                cli.jump(cmd, &mut entities);
+        }
+
+        fn _jump(&mut self, entities: &mut EntityList, target: &Position) {
+            if !self.json {
+                println!("Attempting jump to {}", target);
             }
-            v if cli.check_cmd(v, CmdName::JumpMan) => {
-                cli.jump_man(cmd, &mut entities);
+            let player_id = entities.get_player().unwrap().id;
+            let jump = match self.gm.jump(entities, player_id, target) {
+                Ok(jump) => jump,
+                Err(e) => {
+                    self.gm_err("Jump failed", &e);
+                    return;
+                }
+            };
+            let ent = entities.get_player().unwrap();
+            if self.json {
+                let msg = format!(
+                    "Jump successful: traveled {} light years, consumed {} fuel",
+                    jump.distance, jump.fuel_used
+                );
+                let data = json!({
+                    "jump": jump,
+                    "pos": ent.pos,
+                    "fuel_cur": ent.jump_drive.fuel_cur,
+                    "fuel_max": ent.jump_drive.fuel_max,
+                });
+                self.emit(SUCCESS, &msg, data);
+                return;
             }
-            v if cli.check_cmd(v, CmdName::JumpCheck) => {
-                cli.jump_check(cmd, &entities);
+            self.suc("Jump successful");
+            println!("Distance traveled: {} ly", jump.distance);
+            println!("Fuel used: {} g", jump.fuel_used);
+            println!("Current Fuel: {}", ent.jump_drive.fuel_str());
+            println!("New Position: {}", ent.pos);
+        }
+        fn cli_header(&self, title: &str) {
+            if self.json {
+                return;
             }
-            v if cli.check_cmd(v, CmdName::JumpCheckMan) => {
-                cli.jump_check_man(cmd, &entities);
+            println!("{}", "▀".repeat(64).green());
+            println!(
+                "██▀{:^67}▄██",
+                format!("⏣ |⯟ |- {} -|⯟ |⏣", title.to_uppercase().bright_green())
+            );
+            println!("{}", "▄".repeat(64).green());
+            println!();
+        }
+    }
+}
+
+// JSON-RPC 2.0 over a localhost socket, one request per line
+// Every connection shares one game, a request holds the lock until it's answered
+mod server {
+    use serde::Deserialize;
+    use serde::Serialize;
+    use serde::de::DeserializeOwned;
+    use serde_json::{Value, json};
+    use spacetraders::entity_list::EntityList;
+    use spacetraders::game_state::SessionState;
+    use spacetraders::gm::{GM, GMError};
+    use spacetraders::item_id::ItemId;
+    use spacetraders::pos::Position;
+    use spacetraders::slots::{DEFAULT_SLOT, SaveSlots};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{Ipv4Addr, TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex};
+
+    // Longest request line accepted, anything bigger drops the connection
+    const MAX_LINE: u64 = 64 * 1024;
+
+    // JSON-RPC error codes, -32000 and up are ours
+    const PARSE_ERROR: i32 = -32700;
+    const INVALID_REQUEST: i32 = -32600;
+    const METHOD_NOT_FOUND: i32 = -32601;
+    const INVALID_PARAMS: i32 = -32602;
+    const INTERNAL_ERROR: i32 = -32603;
+    const ACTION_FAILED: i32 = -32000;
+    const UNAUTHORIZED: i32 = -32001;
+
+    // Why every call is refused once a request has panicked
+    const LOST: &str = "Game state lost to an earlier crash";
+
+    // Everything a request can touch
+    pub struct Game {
+        pub gm: GM,
+        pub entities: EntityList,
+        pub session: SessionState,
+        pub slots: SaveSlots,
+    }
+
+    #[derive(Debug)]
+    pub struct RpcError {
+        code: i32,
+        message: String,
+        data: Option<Value>,
+    }
+    impl RpcError {
+        fn new(code: i32, message: &str) -> Self {
+            RpcError {
+                code,
+                message: message.to_string(),
+                data: None,
             }
-            v if cli.check_cmd(v, CmdName::JumpRel) => {
-                cli.jump_rel(cmd, &mut entities);
+        }
+    }
+
+    // A refused action, the error data has the same shape as a --json line
+    impl From<GMError> for RpcError {
+        fn from(e: GMError) -> Self {
+            let message = e.to_string();
+            RpcError {
+                code: ACTION_FAILED,
+                data: Some(json!({ "status": e.kind(), "message": message, "data": e })),
+                message,
             }
-            v if cli.check_cmd(v, CmdName::JumpCheckRel) => {
-                cli.jump_check_rel(cmd, &entities);
+        }
+    }
+
+    // Same shape as a --json line
+    fn ok<T: Serialize>(message: &str, data: T) -> Result<Value, RpcError> {
+        Ok(json!({ "status": "Success", "message": message, "data": data }))
+    }
+
+    // Missing params are the same as {}
+    fn params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+        let params = if params.is_null() { json!({}) } else { params };
+        serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, &e.to_string()))
+    }
+
+    #[derive(Deserialize)]
+    struct AuthParams {
+        token: String,
+    }
+
+    // An entity, or x and y for raw coordinates
+    #[derive(Deserialize)]
+    struct TargetParams {
+        ent_id: Option<i32>,
+        x: Option<i32>,
+        y: Option<i32>,
+    }
+
+    #[derive(Deserialize)]
+    struct EntParams {
+        ent_id: i32,
+    }
+
+    #[derive(Deserialize)]
+    struct MaybeEntParams {
+        ent_id: Option<i32>,
+    }
+
+    #[derive(Deserialize)]
+    struct TradeParams {
+        ent_id: i32,
+        item: ItemId,
+        qty: i32,
+    }
+
+    #[derive(Deserialize)]
+    struct RefuelParams {
+        amount: Option<i32>,
+    }
+
+    #[derive(Deserialize)]
+    struct ListParams {
+        max_distance: Option<i32>,
+    }
+
+    #[derive(Deserialize)]
+    struct SaveParams {
+        slot: Option<String>,
+    }
+
+    fn target_pos(game: &Game, target: &TargetParams) -> Result<Position, RpcError> {
+        match (target.ent_id, target.x, target.y) {
+            (Some(ent_id), None, None) => match game.entities.get_by_id(ent_id) {
+                Some(ent) => Ok(ent.pos),
+                None => Err(GMError::UnknownEntity { ent_id }.into()),
+            },
+            (None, Some(x), Some(y)) => {
+                let pos = Position::new(x, y);
+                if !game.gm.univ().in_bounds(&pos) {
+                    let size = game.gm.univ().gal_size;
+                    return Err(RpcError::new(
+                        INVALID_PARAMS,
+                        &format!("x and y must be within -{} to {}", size, size),
+                    ));
+                }
+                Ok(pos)
             }
-            v if cli.check_cmd(v, CmdName::Entities) => {
-                cli.entities(cmd, &entities);
+            _ => Err(RpcError::new(
+                INVALID_PARAMS,
+                "give either ent_id or x and y",
+            )),
+        }
+    }
+
+    fn player_id(game: &Game) -> i32 {
+        game.entities.get_player().unwrap().id
+    }
+
+    impl Game {
+        // Run one authenticated method against the game
+        fn call(&mut self, method: &str, raw: Value) -> Result<Value, RpcError> {
+            match method {
+                "jump" => {
+                    let target: TargetParams = params(raw)?;
+                    let pos = target_pos(self, &target)?;
+                    let player_id = player_id(self);
+                    let jump = self.gm.jump(&mut self.entities, player_id, &pos)?;
+                    let ship = self.entities.get_player().unwrap();
+                    let data = json!({
+                        "jump": jump,
+                        "pos": ship.pos,
+                        "fuel_cur": ship.jump_drive.fuel_cur,
+                        "fuel_max": ship.jump_drive.fuel_max,
+                        "tick": self.gm.tick(),
+                    });
+                    ok("Jump successful.", data)
+                }
+                // Whether the jump would work is the answer, not a failure
+                "jump_check" => {
+                    let target: TargetParams = params(raw)?;
+                    let pos = target_pos(self, &target)?;
+                    let check = self
+                        .gm
+                        .jump_check(self.entities.get_player().unwrap(), &pos);
+                    ok("", check)
+                }
+                "dock" => {
+                    let p: EntParams = params(raw)?;
+                    self.gm.dock(&mut self.entities, p.ent_id)?;
+                    ok("Docked.", json!({ "ent_id": p.ent_id }))
+                }
+                "undock" => {
+                    let player_id = player_id(self);
+                    let station_id = self.gm.undock(&mut self.entities, player_id)?;
+                    ok("Undocked.", json!({ "ent_id": station_id }))
+                }
+                "buy" | "sell" => {
+                    let p: TradeParams = params(raw)?;
+                    let player_id = player_id(self);
+                    let trade = if method == "buy" {
+                        self.gm
+                            .buy(&mut self.entities, player_id, p.ent_id, &p.item, p.qty)?
+                    } else {
+                        self.gm
+                            .sell(&mut self.entities, player_id, p.ent_id, &p.item, p.qty)?
+                    };
+                    let data = json!({
+                        "ent_id": p.ent_id,
+                        "item": p.item,
+                        "qty": trade.qty,
+                        "price": trade.price,
+                        "total": trade.total,
+                        "credits": self.entities.get_player().unwrap().fin.credits,
+                    });
+                    ok("Traded.", data)
+                }
+                "refuel" => {
+                    let p: RefuelParams = params(raw)?;
+                    let ship = self.entities.get_player().unwrap();
+                    let (player_id, full) = (ship.id, ship.jump_drive.refuel_amt());
+                    let amount = p.amount.unwrap_or(full);
+                    let refuel = self.gm.refuel(&mut self.entities, player_id, amount)?;
+                    let ship = self.entities.get_player().unwrap();
+                    let data = json!({
+                        "amount": refuel.amount,
+                        "cost": refuel.cost,
+                        "wanted": refuel.wanted,
+                        "fuel_cur": ship.jump_drive.fuel_cur,
+                        "fuel_max": ship.jump_drive.fuel_max,
+                        "credits": ship.fin.credits,
+                    });
+                    ok("Refueled.", data)
+                }
+                "scan" => {
+                    let p: MaybeEntParams = params(raw)?;
+                    let ent = match p.ent_id {
+                        Some(ent_id) => self
+                            .entities
+                            .get_by_id(ent_id)
+                            .ok_or(GMError::UnknownEntity { ent_id })?,
+                        None => self.entities.get_player().unwrap(),
+                    };
+                    ok("", ent)
+                }
+                "entities" => {
+                    let p: ListParams = params(raw)?;
+                    let ship = self.entities.get_player().unwrap();
+                    let max_distance = p.max_distance.unwrap_or(ship.jump_drive.max_range);
+                    let rows: Vec<_> = self
+                        .entities
+                        .list_by_distance(ship.pos, max_distance)
+                        .iter()
+                        .map(|target| {
+                            json!({
+                                "id": target.id,
+                                "class": target.class,
+                                "station_type": target.station_type,
+                                "distance": ship.pos.distance(&target.pos),
+                                "pos": target.pos,
+                                "name": target.name,
+                            })
+                        })
+                        .collect();
+                    ok("", rows)
+                }
+                // Slot names only, a client shouldn't pick paths on this machine
+                "save" => {
+                    let p: SaveParams = params(raw)?;
+                    let slot = p.slot.as_deref().unwrap_or(DEFAULT_SLOT);
+                    match self
+                        .slots
+                        .save(&self.gm, &self.entities, &self.session, slot)
+                    {
+                        Ok(path) => ok(&format!("Saved to {}", path), json!({ "slot": slot })),
+                        Err(e) => Err(e.into()),
+                    }
+                }
+                _ => Err(RpcError::new(
+                    METHOD_NOT_FOUND,
+                    &format!("Unknown method '{}'", method),
+                )),
             }
-            v if cli.check_cmd(v, CmdName::Cargo) => {
-                cli.cargo(cmd, &entities);
+        }
+    }
+
+    // Comparing every byte so the time taken doesn't leak the token
+    fn token_matches(given: &str, token: &str) -> bool {
+        given.len() == token.len()
+            && given
+                .bytes()
+                .zip(token.bytes())
+                .fold(0, |acc, (a, b)| acc | (a ^ b))
+                == 0
+    }
+
+    // A fresh token for when none was given
+    pub fn new_token() -> String {
+        format!("{:032x}", rand::random::<u128>())
+    }
+
+    struct Conn {
+        game: Arc<Mutex<Game>>,
+        token: Arc<String>,
+        authed: bool,
+    }
+    impl Conn {
+        // None for notifications, they get no reply
+        fn handle(&mut self, line: &str) -> Option<Value> {
+            let request: Value = match serde_json::from_str(line) {
+                Ok(request) => request,
+                Err(e) => {
+                    return Some(reply(
+                        Value::Null,
+                        Err(RpcError::new(PARSE_ERROR, &e.to_string())),
+                    ));
+                }
+            };
+            let id = request.get("id").cloned();
+            let method = request.get("method").and_then(Value::as_str);
+            match method {
+                Some(method) if request.get("jsonrpc") == Some(&json!("2.0")) => {
+                    let raw = request.get("params").cloned().unwrap_or(Value::Null);
+                    let res = self.call(method, raw);
+                    id.map(|id| reply(id, res))
+                }
+                // Batches aren't supported either
+                _ => {
+                    let err = RpcError::new(INVALID_REQUEST, "not a JSON-RPC 2.0 request");
+                    Some(reply(id.unwrap_or(Value::Null), Err(err)))
+                }
             }
-            v if cli.check_cmd(v, CmdName::Buy) => {
-                cli.buy(cmd, &mut entities);
+        }
+
+        fn call(&mut self, method: &str, raw: Value) -> Result<Value, RpcError> {
+            if method == "auth" {
+                let p: AuthParams = params(raw)?;
+                self.authed = token_matches(&p.token, &self.token);
+                return if self.authed {
+                    Ok(json!({ "status": "Success", "message": "Authenticated." }))
+                } else {
+                    Err(RpcError::new(UNAUTHORIZED, "Bad token"))
+                };
             }
-            v if cli.check_cmd(v, CmdName::Sell) => {
-                cli.sell(cmd, &mut entities);
+            if !self.authed {
+                return Err(RpcError::new(
+                    UNAUTHORIZED,
+                    "Call auth with the server token first",
+                ));
             }
-            v if cli.check_cmd(v, CmdName::DockList) => {
-                cli.dock_list(cmd, &entities);
+            // A panic mid-request poisons the lock, the game may be half changed
+            let Ok(mut game) = self.game.lock() else {
+                return Err(RpcError::new(INTERNAL_ERROR, LOST));
+            };
+            game.call(method, raw)
+        }
+    }
+
+    fn reply(id: Value, res: Result<Value, RpcError>) -> Value {
+        match res {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(e) => {
+                let mut error = json!({ "code": e.code, "message": e.message });
+                if let Some(data) = e.data {
+                    error["data"] = data;
+                }
+                json!({ "jsonrpc": "2.0", "id": id, "error": error })
             }
-            v if cli.check_cmd(v, CmdName::Dock) => {
-                cli.dock(cmd, &mut entities);
+        }
+    }
+
+    fn serve_conn(stream: TcpStream, mut conn: Conn) -> std::io::Result<()> {
+        let mut writer = stream.try_clone()?;
+        let mut reader = BufReader::new(stream);
+        loop {
+            let mut line = String::new();
+            let read = reader.by_ref().take(MAX_LINE).read_line(&mut line)?;
+            if read == 0 {
+                return Ok(());
             }
-            v if cli.check_cmd(v, CmdName::Undock) => {
-                cli.undock(cmd, &mut entities);
+            if !line.ends_with('\n') && read as u64 == MAX_LINE {
+                let err = RpcError::new(INVALID_REQUEST, "request too long");
+                writeln!(writer, "{}", reply(Value::Null, Err(err)))?;
+                return Ok(());
             }
-            v if cli.check_cmd(v, CmdName::Refuel) => {
-                cli.refuel(cmd, &mut entities);
+            if line.trim().is_empty() {
+                continue;
             }
-            v if cli.check_cmd(v, CmdName::Name) => {
-                cli.name(cmd, &mut entities);
+            if let Some(response) = conn.handle(&line) {
+                writeln!(writer, "{}", response)?;
             }
-            v if cli.check_cmd(v, CmdName::Time) => {
-                cli.time(cmd);
+            if conn.game.is_poisoned() {
+                return Err(std::io::Error::other(LOST));
             }
-            v if cli.check_cmd(v, CmdName::Save) => {
-                cli.save(cmd, &entities);
+        }
+    }
+
+    // Blocks until a request panics, one thread per client
+    pub fn serve(port: u16, token: String, game: Game) -> std::io::Result<()> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        println!("Serving JSON-RPC on {}", listener.local_addr()?);
+        println!("Token: {}", token);
+        let game = Arc::new(Mutex::new(game));
+        let token = Arc::new(token);
+        for stream in listener.incoming() {
+            // Stop taking clients once a crash has left the game unusable
+            if game.is_poisoned() {
+                return Err(std::io::Error::other(LOST));
+            }
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    println!("Connection failed: {}", e);
+                    continue;
+                }
+            };
+            let conn = Conn {
+                game: Arc::clone(&game),
+                token: Arc::clone(&token),
+                authed: false,
+            };
+            std::thread::spawn(move || {
+                let peer = stream.peer_addr().ok();
+                if let Err(e) = serve_conn(stream, conn) {
+                    println!("Connection {:?} closed: {}", peer, e);
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+// Command line flags
+// Usage text is built from FLAGS and univ::FLAGS so it can't fall out of date
+mod args {
+    use spacetraders::univ;
+
+    pub struct FlagSpec {
+        pub long: &'static str,
+        pub short: Option<&'static str>,
+        pub value: Option<&'static str>, // Placeholder, None for switches
+        pub desc: &'static str,
+    }
+
+    pub const FLAGS: [FlagSpec; 14] = [
+        FlagSpec {
+            long: "--seed",
+            short: Some("-s"),
+            value: Some("<n>"),
+            desc: "galaxy seed, the same seed gives the same galaxy",
+        },
+        FlagSpec {
+            long: "--load",
+            short: Some("-l"),
+            value: Some("<save>"),
+            desc: "start from a save slot or save file",
+        },
+        FlagSpec {
+            long: "--config",
+            short: Some("-c"),
+            value: Some("<file>"),
+            desc: "universe config (default <data-dir>/universe.json)",
+        },
+        FlagSpec {
+            long: "--script",
+            short: None,
+            value: Some("<file>"),
+            desc: "run commands from a file instead of the prompt",
+        },
+        FlagSpec {
+            long: "--stop-on-fail",
+            short: None,
+            value: None,
+            desc: "with --script, stop at the first failing command",
+        },
+        FlagSpec {
+            long: "--echo",
+            short: None,
+            value: None,
+            desc: "with --script, print each command before running it",
+        },
+        FlagSpec {
+            long: "--json",
+            short: None,
+            value: None,
+            desc: "print each result as one line of JSON",
+        },
+        FlagSpec {
+            long: "--serve",
+            short: None,
+            value: Some("<port>"),
+            desc: "serve JSON-RPC to bots on localhost instead of the prompt",
+        },
+        FlagSpec {
+            long: "--token",
+            short: None,
+            value: Some("<token>"),
+            desc: "with --serve, the token clients must send (default random)",
+        },
+        FlagSpec {
+            long: "--no-color",
+            short: None,
+            value: None,
+            desc: "plain output without colors",
+        },
+        FlagSpec {
+            long: "--names",
+            short: None,
+            value: Some("<file>"),
+            desc: "station name list (default <data-dir>/names.txt)",
+        },
+        FlagSpec {
+            long: "--data-dir",
+            short: None,
+            value: Some("<dir>"),
+            desc: "game data directory (default res)",
+        },
+        FlagSpec {
+            long: "--help",
+            short: Some("-h"),
+            value: None,
+            desc: "show this help",
+        },
+        FlagSpec {
+            long: "--version",
+            short: Some("-V"),
+            value: None,
+            desc: "show the version",
+        },
+    ];
+
+    #[derive(Debug, Default)]
+    pub struct Args {
+        pub seed: Option<u64>,
+        pub load: Option<String>,
+        pub config: Option<String>,
+        pub script: Option<String>,
+        pub stop_on_fail: bool,
+        pub echo: bool,
+        pub json: bool,
+        pub serve: Option<u16>,
+        pub token: Option<String>,
+        pub no_color: bool,
+        pub names: Option<String>,
+        pub data_dir: Option<String>,
+        // univ::FLAGS overrides, in the order given
+        pub univ: Vec<(String, String)>,
+    }
+
+    pub enum Parsed {
+        Run(Args),
+        Help,
+        Version,
+    }
+
+    #[derive(Debug)]
+    pub enum ArgsError {
+        Unknown(String),
+        MissingValue(String),
+        BadValue { flag: String, value: String },
+        NoValue(String), // A switch was given a value
+        Conflict(String, String),
+    }
+    impl std::fmt::Display for ArgsError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                ArgsError::Unknown(flag) => write!(f, "unknown flag {}", flag),
+                ArgsError::MissingValue(flag) => write!(f, "{} needs a value", flag),
+                ArgsError::BadValue { flag, value } => {
+                    write!(f, "bad value '{}' for {}", value, flag)
+                }
+                ArgsError::NoValue(flag) => write!(f, "{} doesn't take a value", flag),
+                ArgsError::Conflict(flag, other) => {
+                    write!(f, "{} can't be used with {}", flag, other)
+                }
             }
-            v if cli.check_cmd(v, CmdName::Load) => {
-                cli.load(cmd, &mut entities);
+        }
+    }
+
+    // Read by main, listed in the usage text
+    pub const SEED_VAR: &str = "SPACETRADERS_SEED";
+
+    pub fn version() -> String {
+        format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+    }
+
+    pub fn usage() -> String {
+        let mut rows: Vec<(String, &str)> = FLAGS
+            .iter()
+            .map(|spec| {
+                let mut left = match spec.short {
+                    Some(short) => format!("{}, {}", short, spec.long),
+                    None => format!("    {}", spec.long),
+                };
+                if let Some(value) = spec.value {
+                    left = format!("{} {}", left, value);
+                }
+                (left, spec.desc)
+            })
+            .collect();
+        let univ_start = rows.len();
+        rows.extend(
+            univ::FLAGS
+                .iter()
+                .map(|(flag, desc)| (format!("    {} <n>", flag), *desc)),
+        );
+        let width = rows.iter().map(|(left, _)| left.len()).max().unwrap_or(0);
+        let mut out = format!(
+            "{}\nUsage: {} [options]\n\nOptions:\n",
+            version(),
+            env!("CARGO_PKG_NAME")
+        );
+        for (i, (left, desc)) in rows.iter().enumerate() {
+            if i == univ_start {
+                out.push_str("\nUniverse (override the config):\n");
+            }
+            out.push_str(&format!("  {:<width$}  {}\n", left, desc, width = width));
+        }
+        out.push_str("\nEnvironment:\n");
+        out.push_str(&format!(
+            "  {:<width$}  {}\n",
+            SEED_VAR,
+            "galaxy seed when --seed isn't given",
+            width = width
+        ));
+        out
+    }
+
+    // Flags take `--flag value` or `--flag=value`
+    pub fn parse(raw: &[String]) -> Result<Parsed, ArgsError> {
+        let mut args = Args::default();
+        let mut iter = raw.iter();
+        while let Some(arg) = iter.next() {
+            let (name, inline) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (arg.as_str(), None),
+            };
+            let spec = FLAGS
+                .iter()
+                .find(|spec| spec.long == name || spec.short == Some(name));
+            let takes_value = match spec {
+                Some(spec) => spec.value.is_some(),
+                None if univ::FLAGS.iter().any(|(flag, _)| *flag == name) => true,
+                None => return Err(ArgsError::Unknown(name.to_string())),
+            };
+            let value = if takes_value {
+                match inline {
+                    Some(value) => value,
+                    None => iter
+                        .next()
+                        .cloned()
+                        .ok_or_else(|| ArgsError::MissingValue(name.to_string()))?,
+                }
+            } else if inline.is_some() {
+                return Err(ArgsError::NoValue(name.to_string()));
+            } else {
+                String::new()
+            };
+            let long = spec.map_or(name, |spec| spec.long);
+            match long {
+                "--seed" => {
+                    let seed = value.parse().map_err(|_| ArgsError::BadValue {
+                        flag: long.to_string(),
+                        value: value.clone(),
+                    })?;
+                    args.seed = Some(seed);
+                }
+                "--load" => args.load = Some(value),
+                "--config" => args.config = Some(value),
+                "--script" => args.script = Some(value),
+                "--stop-on-fail" => args.stop_on_fail = true,
+                "--echo" => args.echo = true,
+                "--json" => args.json = true,
+                "--serve" => {
+                    let port = value.parse().map_err(|_| ArgsError::BadValue {
+                        flag: long.to_string(),
+                        value: value.clone(),
+                    })?;
+                    args.serve = Some(port);
+                }
+                "--token" if value.is_empty() => {
+                    return Err(ArgsError::BadValue {
+                        flag: long.to_string(),
+                        value,
+                    });
+                }
+                "--token" => args.token = Some(value),
+                "--no-color" => args.no_color = true,
+                "--names" => args.names = Some(value),
+                "--data-dir" => args.data_dir = Some(value),
+                "--help" => return Ok(Parsed::Help),
+                "--version" => return Ok(Parsed::Version),
+                _ => args.univ.push((long.to_string(), value)),
+            }
+        }
+        // A save brings its own galaxy and universe, these would be ignored
+        if args.load.is_some() {
+            let ignored = [
+                args.seed.is_some().then_some("--seed"),
+                args.config.is_some().then_some("--config"),
+            ];
+            let flag = ignored
+                .into_iter()
+                .flatten()
+                .chain(args.univ.iter().map(|(flag, _)| flag.as_str()))
+                .next();
+            if let Some(flag) = flag {
+                return Err(ArgsError::Conflict(flag.to_string(), "--load".to_string()));
             }
-            v if cli.check_cmd(v, CmdName::Quit) => {
-                cli.quit(cmd);
-                break;
+        }
+        Ok(Parsed::Run(args))
+    }
+}
+
+use crate::args::{Args, Parsed};
+use crate::cli::{Flow, ScriptOpts};
+use crate::input::Prompt;
+use spacetraders::entity_list::EntityList;
+use spacetraders::gm::GM;
+use spacetraders::item_meta::{self, InvListMeta, ItemsError};
+use spacetraders::univ::{ConfigError, Univ};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// Universe settings from --config or the data dir, with flags on top
+fn load_universe(args: &Args, data_dir: &Path) -> Result<Univ, ConfigError> {
+    let mut univ = match &args.config {
+        Some(path) => Univ::load(path)?,
+        None => match Univ::load(&data_dir.join("universe.json").to_string_lossy()) {
+            Ok(univ) => univ,
+            Err(ConfigError::Io(e)) if e.kind == io::ErrorKind::NotFound => Univ::default(),
+            Err(e) => return Err(e),
+        },
+    };
+    for (flag, value) in &args.univ {
+        univ.set_flag(flag, value)?;
+    }
+    Ok(univ)
+}
+
+// Startup went wrong, nothing to clean up yet
+fn fail(message: impl std::fmt::Display) -> ! {
+    eprintln!("Error: {}", message);
+    std::process::exit(1);
+}
+
+fn main() {
+    let raw: Vec<String> = std::env::args().skip(1).collect();
+    let args = match args::parse(&raw) {
+        Ok(Parsed::Run(args)) => args,
+        Ok(Parsed::Help) => {
+            print!("{}", args::usage());
+            return;
+        }
+        Ok(Parsed::Version) => {
+            println!("{}", args::version());
+            return;
+        }
+        Err(e) => fail(format!("{}\nTry --help", e)),
+    };
+    if args.no_color {
+        colored::control::set_override(false);
+    }
+    let data_dir = PathBuf::from(args.data_dir.as_deref().unwrap_or("res"));
+
+    let univ = match load_universe(&args, &data_dir) {
+        Ok(univ) => univ,
+        Err(e) => fail(e),
+    };
+
+    // Load the item catalogue (items.json), built-in items if there's no file
+    // Has to happen before any entity is made
+    match InvListMeta::load(&data_dir.join("items.json").to_string_lossy()) {
+        Ok(catalogue) => {
+            if !item_meta::install(catalogue) {
+                fail("item catalogue was loaded after the built-in items were in use");
             }
-            _ => {
-                cli.unknown(cmd);
+        }
+        Err(ItemsError::Io(e)) if e.kind == io::ErrorKind::NotFound => {}
+        Err(e) => fail(e),
+    }
+
+    // The same seed always generates the same galaxy
+    let seed: u64 = match args.seed {
+        Some(seed) => seed,
+        // Placeholder, the save replaces it
+        None if args.load.is_some() => {
+            if std::env::var_os(args::SEED_VAR).is_some() {
+                eprintln!("Warning: {} is ignored with --load", args::SEED_VAR);
             }
+            0
+        }
+        None => match std::env::var(args::SEED_VAR) {
+            Ok(val) => match val.parse() {
+                Ok(num) => num,
+                Err(_) => fail(format!("{} must be a number", args::SEED_VAR)),
+            },
+            Err(_) => rand::random(),
+        },
+    };
+    let mut gm = GM::new(seed, univ);
+    let mut entities = EntityList::new();
+
+    // A loaded game brings its own galaxy
+    if args.load.is_none() {
+        // Load names from file (names.txt)
+        let names_path = match &args.names {
+            Some(path) => PathBuf::from(path),
+            None => data_dir.join("names.txt"),
+        };
+        let name_list: Vec<String> = match std::fs::read_to_string(&names_path) {
+            Ok(contents) => contents
+                .lines()
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .collect(),
+            Err(_) => fail(format!("Unable to read {}", names_path.display())),
+        };
+        if name_list.is_empty() {
+            fail(format!("No station names in {}", names_path.display()));
+        }
+        gm.new_galaxy(&mut entities, &name_list);
+    }
+
+    let mut cli = cli::CLI::new(gm);
+    cli.json = args.json;
+    if let Some(save) = &args.load
+        && let Err(e) = cli.load_game(save, &mut entities)
+    {
+        fail(format!("Unable to load {}: {}", save, e));
+    }
+
+    // Server mode, bots play instead of the prompt
+    if let Some(port) = args.serve {
+        let token = args.token.clone().unwrap_or_else(server::new_token);
+        let game = server::Game {
+            gm: cli.gm,
+            entities,
+            session: cli.session,
+            slots: cli.slots,
+        };
+        if let Err(e) = server::serve(port, token, game) {
+            fail(format!("Unable to serve on port {}: {}", port, e));
+        }
+        return;
+    }
+
+    // Batch mode, the game ends with the script
+    if let Some(path) = &args.script {
+        let opts = ScriptOpts {
+            stop_on_fail: args.stop_on_fail,
+            echo: args.echo,
+        };
+        cli.run_script(path, &opts, &mut entities);
+        if cli.failed() {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    cli.intro();
+
+    let mut prompt = Prompt::new();
+    loop {
+        if !cli.json {
+            println!();
+        }
+        // Tab completes the IDs of anything in jump range
+        let ship = entities.get_player().unwrap();
+        let nearby = entities
+            .list_by_distance(ship.pos, ship.jump_drive.max_range)
+            .iter()
+            .filter(|ent| ent.id != ship.id)
+            .map(|ent| (ent.id, ent.name.clone()))
+            .collect();
+        prompt.set_nearby(nearby);
+        let Some(cmd_raw) = prompt.read(!cli.json) else {
+            break;
+        };
+        if cmd_raw.is_empty() {
+            continue;
+        }
+        if !cli.json {
+            // Clear screen
+            print!("\x1B[2J\x1B[1;1H");
+            // Flush stdout
+            io::stdout().flush().unwrap();
+        }
+
+        if let Flow::Quit = cli.dispatch(&mut entities, &cmd_raw) {
+            break;
         }
     }
 }
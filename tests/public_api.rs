@@ -0,0 +1,95 @@
+// Drives the library the way a frontend does, through GM and read-only lookups
+
+use spacetraders::entity::Entity;
+use spacetraders::entity_list::EntityList;
+use spacetraders::entity_maker;
+use spacetraders::game_state::SessionState;
+use spacetraders::gm::{GM, GMError};
+use spacetraders::pos::Position;
+use spacetraders::univ::Univ;
+
+// The player's ship at the origin and one station within jump range
+fn small_galaxy() -> (GM, EntityList) {
+    let gm = GM::new(1, Univ::default());
+    let mut entities = EntityList::new();
+    entities.add(entity_maker::ship("Ship", gm.univ()));
+    let mut station = Entity::new("Depot");
+    station.set_pos(Position::new(60, 80));
+    station.flags.has_dock = true;
+    entities.add(station);
+    (gm, entities)
+}
+
+fn ids_near(entities: &EntityList, pos: Position, max: i32) -> Vec<i32> {
+    let mut ids: Vec<i32> = entities
+        .list_by_distance(pos, max)
+        .iter()
+        .map(|ent| ent.id)
+        .collect();
+    ids.sort();
+    ids
+}
+
+#[test]
+fn jump_dock_refuel_and_undock() {
+    let (mut gm, mut entities) = small_galaxy();
+    let station_pos = entities.get_by_id(1).unwrap().pos;
+
+    let jump = gm.jump(&mut entities, 0, &station_pos).unwrap();
+    assert_eq!(jump.distance, 100);
+    assert_eq!(gm.tick(), 1);
+    // Lookups see the ship where it ended up
+    assert_eq!(ids_near(&entities, station_pos, 0), vec![0, 1]);
+    assert!(ids_near(&entities, Position::new(0, 0), 10).is_empty());
+
+    gm.dock(&mut entities, 1).unwrap();
+    let refuel = gm.refuel(&mut entities, 0, 1000).unwrap();
+    assert_eq!(refuel.amount, 1000);
+    let ship = entities.get_player().unwrap();
+    assert_eq!(ship.jump_drive.fuel_cur, ship.jump_drive.fuel_max);
+    assert_eq!(ship.fin.credits, gm.univ().start_credits - refuel.cost);
+
+    assert_eq!(gm.undock(&mut entities, 0).unwrap(), 1);
+    assert!(matches!(
+        gm.undock(&mut entities, 0),
+        Err(GMError::NotDocked { .. })
+    ));
+}
+
+#[test]
+fn loaded_games_keep_lookups_current() {
+    let (mut gm, mut entities) = small_galaxy();
+    let away = Position::new(-90, 0);
+    gm.jump(&mut entities, 0, &away).unwrap();
+    assert_eq!(ids_near(&entities, away, 0), vec![0]);
+    assert_eq!(ids_near(&entities, Position::new(60, 80), 50), vec![1]);
+
+    let path = std::env::temp_dir().join(format!("spacetraders-api-{}.json", std::process::id()));
+    let path = path.to_string_lossy();
+    let mut session = SessionState::default();
+    gm.save(&entities, &session, &path).unwrap();
+    let (mut loaded_gm, mut loaded) = (GM::new(2, Univ::default()), EntityList::new());
+    let res = loaded_gm.load(&mut loaded, &mut session, &path);
+    let _ = std::fs::remove_file(path.as_ref());
+    res.unwrap();
+
+    assert_eq!(loaded_gm.tick(), 1);
+    assert_eq!(loaded_gm.seed(), Some(1));
+    assert_eq!(ids_near(&loaded, away, 0), vec![0]);
+    assert_eq!(ids_near(&loaded, Position::new(0, 0), 95), vec![0]);
+}
+
+#[test]
+fn rename_and_unknown_entities() {
+    let (gm, mut entities) = small_galaxy();
+    gm.name_ent(&mut entities, 0, "Nova").unwrap();
+    assert_eq!(entities.get_player().unwrap().name, "Nova");
+    assert!(matches!(
+        gm.name_ent(&mut entities, 42, "Ghost"),
+        Err(GMError::UnknownEntity { ent_id: 42 })
+    ));
+    assert!(matches!(
+        gm.refuel(&mut entities, 0, 100),
+        Err(GMError::NotDocked { .. })
+    ));
+}